@@ -0,0 +1,213 @@
+// ---------------- ICC catalogue (--icc-dir) and substitutions (--icc-map) ----------------
+//
+// Some scanners embed broken or mislabeled profiles. The catalogue indexes a
+// directory of known profiles by MD5 and description; the map file says which
+// embedded profiles must be replaced, and by what.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::icc::BUILTIN_PREFIX;
+
+/// Map-file key prefix matching the profile description instead of MD5/ID.
+const DESC_PREFIX: &str = "desc:";
+
+#[derive(Default)]
+pub struct IccCatalog {
+    /// MD5 of the profile file (lowercase hex) -> path
+    by_md5: HashMap<String, PathBuf>,
+    /// Profile description (lowercase) -> path
+    by_desc: HashMap<String, PathBuf>,
+    /// Map-file key (MD5/Profile ID hex, or `desc:` + lowercase description)
+    /// -> replacement (profile path or `builtin:<name>`)
+    substitutions: HashMap<String, PathBuf>,
+}
+
+/// A catalogue match for an embedded profile.
+pub struct Substitution<'a> {
+    /// Map-file key that matched.
+    pub key: String,
+    /// Replacement profile path or `builtin:<name>`.
+    pub replacement: &'a Path,
+}
+
+impl IccCatalog {
+    /// Index `icc_dir` (recursively, `.icc`/`.icm`) and read the substitution map.
+    pub fn load(icc_dir: Option<&Path>, map: Option<&Path>) -> Result<Self> {
+        let mut cat = Self::default();
+        if let Some(dir) = icc_dir {
+            cat.index_dir(dir)?;
+        }
+        if let Some(map) = map {
+            cat.read_map(map)?;
+        }
+        Ok(cat)
+    }
+
+    /// (indexed profiles, substitution rules)
+    pub fn stats(&self) -> (usize, usize) {
+        (self.by_md5.len(), self.substitutions.len())
+    }
+
+    fn index_dir(&mut self, dir: &Path) -> Result<()> {
+        if !dir.is_dir() {
+            bail!("--icc-dir is not a directory: {}", dir.display());
+        }
+        for entry in WalkDir::new(dir).follow_links(true) {
+            let entry = entry.with_context(|| format!("Walk {}", dir.display()))?;
+            let path = entry.path();
+            if !entry.file_type().is_file()
+                || !matches!(crate::file_ext_lower(path).as_str(), "icc" | "icm")
+            {
+                continue;
+            }
+            let bytes = fs::read(path).with_context(|| format!("Read {}", path.display()))?;
+            let desc = match lcms2::Profile::new_icc(&bytes) {
+                Ok(prof) => description(&prof),
+                Err(e) => {
+                    eprintln!("Warning: skipping unreadable ICC profile {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            self.by_md5
+                .insert(crate::hex(&md5::compute(&bytes).0), path.to_path_buf());
+            if let Some(desc) = desc {
+                self.by_desc.insert(desc.to_lowercase(), path.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    /// Map file format, one rule per line (`#` at the start of a line or after
+    /// whitespace starts a comment, `\#` is a literal `#`):
+    ///
+    /// ```text
+    /// <md5 or profile ID, 32 hex digits> = <replacement>
+    /// desc:<profile description>          = <replacement>
+    /// ```
+    ///
+    /// The replacement is `builtin:<name>`, a profile path (relative to the map
+    /// file), or the MD5 or description of a profile in the catalogue.
+    fn read_map(&mut self, map: &Path) -> Result<()> {
+        let text = fs::read_to_string(map)
+            .with_context(|| format!("Read ICC substitution map {}", map.display()))?;
+        let base = map.parent().unwrap_or(Path::new(""));
+
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, repl) = line
+                .split_once('=')
+                .with_context(|| format!("{}:{}: expected `<key> = <replacement>`", map.display(), n + 1))?;
+            let key = normalize_key(key.trim())
+                .with_context(|| format!("{}:{}: bad key", map.display(), n + 1))?;
+            let repl = self
+                .resolve(base, repl.trim())
+                .with_context(|| format!("{}:{}: bad replacement", map.display(), n + 1))?;
+            self.substitutions.insert(key, repl);
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, base: &Path, repl: &str) -> Result<PathBuf> {
+        if repl.starts_with(BUILTIN_PREFIX) {
+            return Ok(PathBuf::from(repl));
+        }
+        let path = base.join(repl);
+        if path.is_file() {
+            return Ok(path);
+        }
+        let lower = repl.to_lowercase();
+        if let Some(p) = self.by_md5.get(&lower).or_else(|| self.by_desc.get(&lower)) {
+            return Ok(p.clone());
+        }
+        bail!("'{repl}' is neither a file, a builtin: profile nor a catalogue entry")
+    }
+
+    /// Replacement for an embedded profile, matched by MD5 of its bytes, header
+    /// Profile ID, or description (in that order).
+    pub fn substitute(&self, bytes: &[u8], desc: Option<&str>) -> Option<Substitution<'_>> {
+        if self.substitutions.is_empty() {
+            return None;
+        }
+        let mut keys = vec![crate::hex(&md5::compute(bytes).0)];
+        if let Some(id) = header_id(bytes) {
+            keys.push(id);
+        }
+        if let Some(desc) = desc {
+            keys.push(format!("{DESC_PREFIX}{}", desc.to_lowercase()));
+        }
+        keys.into_iter().find_map(|key| {
+            let replacement = self.substitutions.get(&key)?;
+            Some(Substitution { key, replacement })
+        })
+    }
+
+    /// Catalogue file holding a byte-identical profile.
+    pub fn lookup(&self, bytes: &[u8]) -> Option<&Path> {
+        self.by_md5
+            .get(&crate::hex(&md5::compute(bytes).0))
+            .map(PathBuf::as_path)
+    }
+}
+
+/// `line` without its comment. `#` only starts a comment at the start of the
+/// line or after whitespace, so keys such as `desc:Scanner#2` keep theirs.
+fn strip_comment(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    let mut after_space = true;
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' if chars.peek() == Some(&'#') => chars.next().unwrap_or(c),
+            '#' if after_space => break,
+            c => c,
+        };
+        after_space = c.is_whitespace();
+        out.push(c);
+    }
+    out
+}
+
+fn normalize_key(key: &str) -> Result<String> {
+    if let Some(desc) = key.strip_prefix(DESC_PREFIX) {
+        return Ok(format!("{DESC_PREFIX}{}", desc.trim().to_lowercase()));
+    }
+    if key.len() == 32 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(key.to_ascii_lowercase());
+    }
+    bail!("'{key}' is neither 32 hex digits (MD5 / Profile ID) nor desc:<description>")
+}
+
+/// Non-zero Profile ID from the ICC header, as hex.
+fn header_id(bytes: &[u8]) -> Option<String> {
+    let id = bytes.get(84..100)?;
+    id.iter().any(|&b| b != 0).then(|| crate::hex(id))
+}
+
+/// Profile description (`desc` tag), if any.
+pub fn description(prof: &lcms2::Profile) -> Option<String> {
+    prof.info(lcms2::InfoType::Description, lcms2::Locale::none())
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_start_at_line_start_or_after_whitespace() {
+        assert_eq!(strip_comment("# a comment"), "");
+        assert_eq!(strip_comment("desc:Old = builtin:srgb # fixed"), "desc:Old = builtin:srgb ");
+        assert_eq!(strip_comment("desc:Scanner#2 = builtin:srgb"), "desc:Scanner#2 = builtin:srgb");
+        assert_eq!(strip_comment("desc:Model \\#2 = builtin:srgb"), "desc:Model #2 = builtin:srgb");
+        assert_eq!(strip_comment("a\\b = c"), "a\\b = c");
+    }
+}
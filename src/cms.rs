@@ -0,0 +1,316 @@
+// ---------------- ICC transforms (lcms2) ----------------
+//
+// Transforms are built without the lcms2 1-pixel cache (`Flags::NO_CACHE`),
+// which makes them `Sync`: one transform can be shared by all rayon workers,
+// both for chunks of a single image and across files in batch mode.
+
+use anyhow::Result;
+use lcms2::{
+    CIELab, CIELabExt, CIExyY, ColorSpaceSignature, DisallowCache, Flags, GlobalContext, Intent, PixelFormat, Pod, Profile,
+    Transform,
+};
+use rayon::prelude::*;
+use rgb::{RGB, RGB16, RGB8};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Shareable in-place transform for pixels of type `P`.
+pub type SharedTransform<P> = Transform<P, P, GlobalContext, DisallowCache>;
+
+/// Pixels per chunk for the parallel ICC transform.
+const TRANSFORM_CHUNK_PIXELS: usize = 1 << 16;
+
+/// Apply the ICC transform in parallel chunks.
+///
+/// Every pixel is transformed independently, so the result does not depend
+/// on chunking or thread count.
+pub fn transform_in_place_parallel<P: Copy + Pod + Send>(xform: &SharedTransform<P>, pix: &mut [P]) {
+    pix.par_chunks_mut(TRANSFORM_CHUNK_PIXELS)
+        .for_each(|chunk| xform.transform_in_place(chunk));
+}
+
+/// Content digest of a profile: MD5 of its serialized form with the header
+/// fields that do not affect colour (flags, rendering intent, creation date,
+/// profile ID) zeroed.
+///
+/// This matches the ICC Profile ID computation except for the creation date,
+/// which is ignored so that e.g. two `Profile::new_srgb()` instances created
+/// at different times compare equal.
+pub fn profile_digest(prof: &Profile) -> Result<[u8; 16]> {
+    Ok(icc_digest(prof.icc()?))
+}
+
+fn icc_digest(mut bytes: Vec<u8>) -> [u8; 16] {
+    if bytes.len() >= 128 {
+        bytes[24..36].fill(0); // creation date/time
+        bytes[44..48].fill(0); // profile flags
+        bytes[64..68].fill(0); // rendering intent
+        bytes[84..100].fill(0); // profile ID
+    }
+    md5::compute(&bytes).0
+}
+
+/// Profile ID stored in the ICC header (all zeros when not set).
+fn header_profile_id(bytes: &[u8]) -> [u8; 16] {
+    let mut id = [0u8; 16];
+    if bytes.len() >= 100 {
+        id.copy_from_slice(&bytes[84..100]);
+    }
+    id
+}
+
+/// True when both profiles describe the same colour space: byte-identical,
+/// same non-zero header Profile ID, or same content digest.
+pub fn same_profile(a: &Profile, b: &Profile) -> Result<bool> {
+    let (a, b) = (a.icc()?, b.icc()?);
+    if a == b {
+        return Ok(true);
+    }
+    let id = header_profile_id(&a);
+    if id != [0u8; 16] && id == header_profile_id(&b) {
+        return Ok(true);
+    }
+    Ok(icc_digest(a) == icc_digest(b))
+}
+
+/// Buffer format for RGB pixels of format `rgb` under `prof`.
+///
+/// Grayscale sources are decoded as RGB with R = G = B, so a gray profile
+/// reads (or writes) the first sample of each pixel and skips the other two
+/// as extra channels. Colour sources never get a gray input profile
+/// (`convert_page` rejects it).
+fn buffer_format(prof: &Profile, rgb: PixelFormat) -> PixelFormat {
+    if prof.color_space() != ColorSpaceSignature::GrayData {
+        return rgb;
+    }
+    let gray = match rgb {
+        PixelFormat::RGB_8 => PixelFormat::GRAY_8,
+        PixelFormat::RGB_FLT => PixelFormat::GRAY_FLT,
+        _ => PixelFormat::GRAY_16,
+    };
+    PixelFormat(gray.0 | (2 << 7)) // EXTRA_SH(2)
+}
+
+/// Samples per channel for the colour checks (17³ colours, including the cube corners).
+const CHECK_GRID_STEPS: u32 = 17;
+
+fn check_grid() -> Vec<RGB16> {
+    let step = |i: u32| (i * 65535 / (CHECK_GRID_STEPS - 1)) as u16;
+    let mut samples = Vec::with_capacity(CHECK_GRID_STEPS.pow(3) as usize);
+    for r in 0..CHECK_GRID_STEPS {
+        for g in 0..CHECK_GRID_STEPS {
+            for b in 0..CHECK_GRID_STEPS {
+                samples.push(RGB16::new(step(r), step(g), step(b)));
+            }
+        }
+    }
+    samples
+}
+
+/// Lab (v4 encoding) of RGB16 samples under `prof`, through the full pipeline
+/// without precalculation.
+fn to_lab(prof: &Profile, samples: &[RGB16]) -> Result<Vec<[u16; 3]>> {
+    let d50 = CIExyY { x: 0.3457, y: 0.3585, Y: 1.0 };
+    let lab = Profile::new_lab4_context(GlobalContext::new(), &d50)?;
+    let xform: Transform<RGB16, [u16; 3]> = Transform::new_flags(
+        prof,
+        buffer_format(prof, PixelFormat::RGB_16),
+        &lab,
+        PixelFormat::Lab_16,
+        Intent::RelativeColorimetric,
+        Flags::NO_OPTIMIZE,
+    )?;
+    let mut out = vec![[0u16; 3]; samples.len()];
+    xform.transform_pixels(samples, &mut out);
+    Ok(out)
+}
+
+/// (mean, max) CIEDE2000 between two lists of encoded Lab values.
+fn delta_e_stats(a: &[[u16; 3]], b: &[[u16; 3]]) -> (f64, f64) {
+    let (mut sum, mut max) = (0.0f64, 0.0f64);
+    for (a, b) in a.iter().zip(b) {
+        let de = CIELab::from_encoded(a).cie2000_delta_e(&CIELab::from_encoded(b), 1.0, 1.0, 1.0);
+        sum += de;
+        max = max.max(de);
+    }
+    (sum / a.len().max(1) as f64, max)
+}
+
+/// Round-trip error of a transform setting: a grid of input colours is taken
+/// input -> output -> input with `flags` and both ends are compared in Lab
+/// (CIEDE2000). Returns (mean, max).
+///
+/// Colours outside the output gamut also carry their clipping error, so the
+/// figures are meant for comparing settings on the same profile pair.
+pub fn round_trip_error(
+    in_prof: &Profile,
+    out_prof: &Profile,
+    intent: Intent,
+    flags: Flags<DisallowCache>,
+) -> Result<(f64, f64)> {
+    let samples = check_grid();
+
+    let in_format = buffer_format(in_prof, PixelFormat::RGB_16);
+    let fwd: SharedTransform<RGB16> = Transform::new_flags_context(
+        GlobalContext::new(),
+        in_prof,
+        in_format,
+        out_prof,
+        PixelFormat::RGB_16,
+        intent,
+        flags,
+    )?;
+    let back: SharedTransform<RGB16> = Transform::new_flags_context(
+        GlobalContext::new(),
+        out_prof,
+        PixelFormat::RGB_16,
+        in_prof,
+        in_format,
+        intent,
+        flags,
+    )?;
+    let mut round = samples.clone();
+    fwd.transform_in_place(&mut round);
+    back.transform_in_place(&mut round);
+
+    Ok(delta_e_stats(&to_lab(in_prof, &samples)?, &to_lab(in_prof, &round)?))
+}
+
+/// Colour difference between two RGB profiles for the same device values
+/// (CIEDE2000 over a grid of RGB colours). Returns (mean, max).
+pub fn profile_difference(a: &Profile, b: &Profile) -> Result<(f64, f64)> {
+    let samples = check_grid();
+    Ok(delta_e_stats(&to_lab(a, &samples)?, &to_lab(b, &samples)?))
+}
+
+/// Everything that determines the device link lcms2 precalculates.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TransformKey {
+    input: [u8; 16],
+    output: [u8; 16],
+    intent: u32,
+    flags: u32,
+    in_format: u32,
+    out_format: u32,
+}
+
+type CacheSlot<P> = Arc<Mutex<Option<Arc<SharedTransform<P>>>>>;
+
+struct Slots<P>(Mutex<HashMap<TransformKey, CacheSlot<P>>>);
+
+impl<P> Default for Slots<P> {
+    fn default() -> Self {
+        Self(Mutex::new(HashMap::new()))
+    }
+}
+
+/// Transforms shared across files, keyed by profile digests, intent, flags
+/// and pixel formats.
+///
+/// A scanner batch usually carries a single embedded profile, so the device
+/// link is built once and reused for every file.
+#[derive(Default)]
+pub struct TransformCache {
+    rgb8: Slots<RGB8>,
+    rgb16: Slots<RGB16>,
+    rgb_f32: Slots<RGB<f32>>,
+    built: AtomicUsize,
+    reused: AtomicUsize,
+}
+
+impl TransformCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached RGB_8 -> RGB_8 transform (native path for 8-bit sources).
+    pub fn rgb8(
+        &self,
+        in_prof: &Profile,
+        out_prof: &Profile,
+        intent: Intent,
+        flags: Flags<DisallowCache>,
+    ) -> Result<Arc<SharedTransform<RGB8>>> {
+        self.get_or_build(&self.rgb8, PixelFormat::RGB_8, in_prof, out_prof, intent, flags)
+    }
+
+    /// Cached RGB_16 -> RGB_16 transform.
+    pub fn rgb16(
+        &self,
+        in_prof: &Profile,
+        out_prof: &Profile,
+        intent: Intent,
+        flags: Flags<DisallowCache>,
+    ) -> Result<Arc<SharedTransform<RGB16>>> {
+        self.get_or_build(&self.rgb16, PixelFormat::RGB_16, in_prof, out_prof, intent, flags)
+    }
+
+    /// Cached RGB_FLT -> RGB_FLT transform (unbounded for matrix/TRC profiles).
+    pub fn rgb_f32(
+        &self,
+        in_prof: &Profile,
+        out_prof: &Profile,
+        intent: Intent,
+        flags: Flags<DisallowCache>,
+    ) -> Result<Arc<SharedTransform<RGB<f32>>>> {
+        self.get_or_build(&self.rgb_f32, PixelFormat::RGB_FLT, in_prof, out_prof, intent, flags)
+    }
+
+    /// Return the cached transform for this combination, building it on first
+    /// use. Concurrent callers with the same key wait for the first build
+    /// instead of building their own.
+    fn get_or_build<P: Copy + Pod>(
+        &self,
+        slots: &Slots<P>,
+        format: PixelFormat,
+        in_prof: &Profile,
+        out_prof: &Profile,
+        intent: Intent,
+        flags: Flags<DisallowCache>,
+    ) -> Result<Arc<SharedTransform<P>>> {
+        let in_format = buffer_format(in_prof, format);
+        // A gray output only writes the first sample; the caller copies it to G and B.
+        let out_format = buffer_format(out_prof, format);
+        let key = TransformKey {
+            input: profile_digest(in_prof)?,
+            output: profile_digest(out_prof)?,
+            intent: intent as u32,
+            flags: flags.0,
+            in_format: in_format.0,
+            out_format: out_format.0,
+        };
+
+        let slot = {
+            let mut map = slots.0.lock().expect("transform cache poisoned");
+            Arc::clone(map.entry(key).or_default())
+        };
+
+        let mut slot = slot.lock().expect("transform cache slot poisoned");
+        if let Some(xform) = slot.as_ref() {
+            self.reused.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(xform));
+        }
+
+        let xform = Arc::new(Transform::new_flags_context(
+            GlobalContext::new(),
+            in_prof,
+            in_format,
+            out_prof,
+            out_format,
+            intent,
+            flags,
+        )?);
+        *slot = Some(Arc::clone(&xform));
+        self.built.fetch_add(1, Ordering::Relaxed);
+        Ok(xform)
+    }
+
+    /// (transforms built, cache hits)
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.built.load(Ordering::Relaxed),
+            self.reused.load(Ordering::Relaxed),
+        )
+    }
+}
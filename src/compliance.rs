@@ -0,0 +1,168 @@
+// ---------------- Archival presets and compliance checks (--check) ----------------
+//
+// FADGI star levels, Metamorfoze and ISO 19264-1 levels as file-level
+// requirements for master files of reflective originals: format, bit depth,
+// colour space, embedded ICC and resolution. The image-quality aims of the
+// guidelines (tone, noise, SFR, colour accuracy) are measured on targets and
+// are not checked here.
+
+use anyhow::{Context, Result};
+use lcms2::Profile;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::{ResolutionUnit, Tag};
+
+use crate::icc::{self, ChromaticAdaptation};
+use crate::{catalog, cms, normalize_resolution, read_tiff_pages, BitDepth, Preset};
+
+/// Largest mean CIEDE2000 between the embedded profile and an accepted colour
+/// space for the profile to count as that colour space (other vendors' builds
+/// of e.g. eciRGB v2 differ slightly from the built-in one).
+const SAME_SPACE_DE: f64 = 1.0;
+
+/// File-level requirements of an archival preset.
+pub struct Requirements {
+    /// Built-in profile the preset converts to
+    pub colour_space: &'static str,
+    /// Colour spaces (built-in names) accepted by the check
+    pub accepted: &'static [&'static str],
+    /// Bit depth the preset writes
+    pub depth: BitDepth,
+    /// Bits per sample accepted by the check
+    pub accepted_bits: &'static [u8],
+    /// Lowest resolution accepted, in ppi; `None`: any stated resolution
+    pub min_ppi: Option<f64>,
+}
+
+const WIDE_GAMUT: &[&str] = &["ecirgb-v2", "adobe-rgb-1998", "prophoto"];
+
+/// Requirements of an archival preset; `None` for the NDK presets.
+pub fn requirements(preset: Preset) -> Option<&'static Requirements> {
+    Some(match preset {
+        Preset::NdkMc | Preset::NdkUcI | Preset::NdkUcII => return None,
+        Preset::Fadgi4Star => &Requirements {
+            colour_space: "ecirgb-v2",
+            accepted: WIDE_GAMUT,
+            depth: BitDepth::B16,
+            accepted_bits: &[16],
+            min_ppi: Some(400.0),
+        },
+        Preset::Fadgi3Star => &Requirements {
+            colour_space: "ecirgb-v2",
+            accepted: WIDE_GAMUT,
+            depth: BitDepth::B16,
+            accepted_bits: &[8, 16],
+            min_ppi: Some(400.0),
+        },
+        Preset::Fadgi2Star => &Requirements {
+            colour_space: "adobe-rgb-1998",
+            accepted: &["ecirgb-v2", "adobe-rgb-1998", "prophoto", "srgb"],
+            depth: BitDepth::B8,
+            accepted_bits: &[8, 16],
+            min_ppi: Some(300.0),
+        },
+        Preset::Fadgi1Star => &Requirements {
+            colour_space: "srgb",
+            accepted: &["ecirgb-v2", "adobe-rgb-1998", "prophoto", "srgb"],
+            depth: BitDepth::B8,
+            accepted_bits: &[8, 16],
+            min_ppi: Some(150.0),
+        },
+        Preset::Metamorfoze => &Requirements {
+            colour_space: "ecirgb-v2",
+            accepted: &["ecirgb-v2"],
+            depth: BitDepth::B16,
+            accepted_bits: &[16],
+            min_ppi: Some(300.0),
+        },
+        Preset::Iso19264A => &Requirements {
+            colour_space: "ecirgb-v2",
+            accepted: WIDE_GAMUT,
+            depth: BitDepth::B16,
+            accepted_bits: &[16],
+            min_ppi: None,
+        },
+        Preset::Iso19264B => &Requirements {
+            colour_space: "ecirgb-v2",
+            accepted: WIDE_GAMUT,
+            depth: BitDepth::B16,
+            accepted_bits: &[8, 16],
+            min_ppi: None,
+        },
+        Preset::Iso19264C => &Requirements {
+            colour_space: "srgb",
+            accepted: &["ecirgb-v2", "adobe-rgb-1998", "prophoto", "srgb"],
+            depth: BitDepth::B8,
+            accepted_bits: &[8, 16],
+            min_ppi: None,
+        },
+    })
+}
+
+/// Problems of `path` against `req`; empty when the file complies.
+pub fn check_file(path: &Path, req: &Requirements, cat: ChromaticAdaptation) -> Result<Vec<String>> {
+    if !crate::is_tiff_path(path) {
+        return Ok(vec!["not a TIFF master file".into()]);
+    }
+    let mut issues = Vec::new();
+
+    let f = File::open(path).with_context(|| format!("Open {}", path.display()))?;
+    let mut dec = Decoder::new(BufReader::new(f)).context("Read TIFF header")?;
+    match dec.colortype()? {
+        tiff::ColorType::RGB(bits) if req.accepted_bits.contains(&bits) => {}
+        color => issues.push(format!(
+            "{color:?} samples (expected RGB with {} bits per sample)",
+            join(req.accepted_bits.iter().map(u8::to_string))
+        )),
+    }
+    // 1 none, 5 LZW, 8 / 32946 Deflate
+    let compression = dec.find_tag_unsigned::<u16>(Tag::Compression)?.unwrap_or(1);
+    if ![1, 5, 8, 32946].contains(&compression) {
+        issues.push(format!("compression {compression} is not lossless (none, LZW or Deflate)"));
+    }
+
+    let meta = read_tiff_pages(path)?.into_iter().next();
+    match meta.as_ref().and_then(|m| m.icc.as_deref()) {
+        None => issues.push("no embedded ICC profile".into()),
+        Some(bytes) => {
+            let embedded = Profile::new_icc(bytes).context("Parse embedded ICC profile")?;
+            if !matches_any(&embedded, req.accepted, cat)? {
+                let desc = catalog::description(&embedded).unwrap_or_else(|| "(no description)".into());
+                issues.push(format!("colour space '{desc}' (expected {})", join(req.accepted.iter())));
+            }
+        }
+    }
+
+    match normalize_resolution(meta.as_ref()) {
+        None => issues.push("no resolution".into()),
+        Some((unit, x, y)) => {
+            let per_inch = if unit == ResolutionUnit::Centimeter { 2.54 } else { 1.0 };
+            let (x, y) = (x.n as f64 / x.d as f64 * per_inch, y.n as f64 / y.d as f64 * per_inch);
+            if (x - y).abs() > 0.5 {
+                issues.push(format!("unequal resolution {x:.0}x{y:.0} ppi"));
+            }
+            if let Some(min) = req.min_ppi
+                && x.min(y) < min - 0.5
+            {
+                issues.push(format!("{:.0} ppi is below {min} ppi", x.min(y)));
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn matches_any(embedded: &Profile, names: &[&str], cat: ChromaticAdaptation) -> Result<bool> {
+    for name in names {
+        let (mean, _) = cms::profile_difference(embedded, &icc::builtin(name, cat)?)?;
+        if mean <= SAME_SPACE_DE {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn join(items: impl Iterator<Item = impl ToString>) -> String {
+    items.map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,236 @@
+// ---------------- Config file (user-defined presets) ----------------
+//
+// Presets are TOML tables whose keys are long option names:
+//
+//   preset = "web"                  # default preset when --preset is not given
+//
+//   [presets.web]
+//   policy = "ndk-uc-ii"            # ICC policy (ndk-mc, ndk-uc-i, ndk-uc-ii)
+//   intent = "relative"
+//   dither-method = "blue-noise"
+//   out-ext = "jpg"
+//
+// A preset expands to command-line options placed before the user's own, so
+// explicit options win and every value goes through the same parser.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Per-project config, read from the current directory.
+const PROJECT_CONFIG: &str = "nclr.toml";
+
+/// Options a preset cannot set.
+const RESERVED: &[&str] = &["input", "output", "preset", "config"];
+
+pub struct PresetDef {
+    /// ICC policy (a built-in preset name), if given
+    pub policy: Option<String>,
+    options: Table,
+    /// File that defined the preset
+    pub source: PathBuf,
+}
+
+#[derive(Default)]
+pub struct Config {
+    presets: BTreeMap<String, PresetDef>,
+    /// Top-level `preset`: used when --preset is not given
+    pub default_preset: Option<String>,
+}
+
+impl Config {
+    /// Read `explicit`, or else the user config and then `./nclr.toml`
+    /// (a preset defined in both is taken from the project file).
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        let mut config = Self::default();
+        match explicit {
+            Some(path) => config.read(path)?,
+            None => {
+                for path in user_config_path().into_iter().chain([PathBuf::from(PROJECT_CONFIG)]) {
+                    if path.is_file() {
+                        config.read(&path)?;
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn preset(&self, name: &str) -> Option<&PresetDef> {
+        self.presets.get(name)
+    }
+
+    pub fn preset_names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    fn read(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).with_context(|| format!("Read config {}", path.display()))?;
+        let mut table: Table = text.parse().with_context(|| format!("Parse config {}", path.display()))?;
+
+        if let Some(name) = table.remove("preset") {
+            let Value::String(name) = name else {
+                bail!("{}: `preset` must be a preset name", path.display());
+            };
+            self.default_preset = Some(name);
+        }
+        if let Some(presets) = table.remove("presets") {
+            let Value::Table(presets) = presets else {
+                bail!("{}: `presets` must be a table", path.display());
+            };
+            for (name, options) in presets {
+                let Value::Table(mut options) = options else {
+                    bail!("{}: presets.{name} must be a table", path.display());
+                };
+                let policy = match options.remove("policy") {
+                    None => None,
+                    Some(Value::String(policy)) => Some(policy),
+                    Some(_) => bail!("{}: presets.{name}.policy must be a preset name", path.display()),
+                };
+                let def = PresetDef { policy, options, source: path.to_path_buf() };
+                self.presets.insert(name, def);
+            }
+        }
+        if let Some(key) = table.keys().next() {
+            bail!("{}: unknown key `{key}` (expected `preset` or `[presets.<name>]`)", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl PresetDef {
+    /// Command-line options equivalent to the preset, checked against `cmd`.
+    pub fn cli_args(&self, cmd: &clap::Command) -> Result<Vec<OsString>> {
+        let mut out = Vec::new();
+        for (key, value) in &self.options {
+            let arg = cmd
+                .get_arguments()
+                .find(|a| a.get_long() == Some(key.as_str()) && !RESERVED.contains(&key.as_str()))
+                .ok_or_else(|| anyhow!("{}: unknown option `{key}`", self.source.display()))?;
+            let flag = format!("--{key}");
+            if !arg.get_action().takes_values() {
+                // Switches can only be turned on
+                match value {
+                    Value::Boolean(true) => out.push(flag.into()),
+                    Value::Boolean(false) => bail!(
+                        "{}: `{key}` is a switch a preset can only turn on (remove `{key} = false`)",
+                        self.source.display()
+                    ),
+                    _ => bail!("{}: `{key}` is a switch (true)", self.source.display()),
+                }
+                continue;
+            }
+            let values = match value {
+                Value::Array(items) => items.iter().map(|v| scalar(key, v)).collect::<Result<Vec<_>>>(),
+                v => scalar(key, v).map(|s| vec![s]),
+            }
+            .with_context(|| format!("{}", self.source.display()))?;
+            for v in values {
+                out.push(format!("{flag}={v}").into());
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => bail!("`{key}`: expected a string, number or boolean"),
+    }
+}
+
+/// `<config dir>/nclr/config.toml`: %APPDATA% on Windows, else
+/// $XDG_CONFIG_HOME or ~/.config.
+fn user_config_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    }?;
+    Some(dir.join("nclr").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction, Command};
+
+    fn command() -> Command {
+        Command::new("nclr")
+            .arg(Arg::new("bpc").long("bpc").action(ArgAction::Set).num_args(0..=1))
+            .arg(Arg::new("input").long("input"))
+            .arg(Arg::new("derivative").long("derivative").action(ArgAction::Append))
+            .arg(Arg::new("intent").long("intent"))
+            .arg(Arg::new("overwrite").long("overwrite").action(ArgAction::SetTrue))
+            .arg(Arg::new("tile-quality").long("tile-quality"))
+            .arg(Arg::new("verify").long("verify").action(ArgAction::SetTrue))
+    }
+
+    fn preset(toml: &str) -> PresetDef {
+        PresetDef { policy: None, options: toml.parse().unwrap(), source: PathBuf::from("nclr.toml") }
+    }
+
+    fn args(toml: &str) -> Result<Vec<String>> {
+        let args = preset(toml).cli_args(&command())?;
+        Ok(args.into_iter().map(|a| a.into_string().unwrap()).collect())
+    }
+
+    #[test]
+    fn options_become_flags() {
+        let toml = r#"
+            bpc = false
+            derivative = ["preset=ndk-mc,suffix=_mc", "preset=ndk-uc-ii,ext=jpg"]
+            intent = "relative"
+            overwrite = true
+            tile-quality = 85
+        "#;
+        assert_eq!(
+            args(toml).unwrap(),
+            [
+                "--bpc=false",
+                "--derivative=preset=ndk-mc,suffix=_mc",
+                "--derivative=preset=ndk-uc-ii,ext=jpg",
+                "--intent=relative",
+                "--overwrite",
+                "--tile-quality=85",
+            ]
+        );
+    }
+
+    #[test]
+    fn floats_and_booleans_as_values() {
+        let toml = "intent = 0.5\ntile-quality = true";
+        assert_eq!(args(toml).unwrap(), ["--intent=0.5", "--tile-quality=true"]);
+    }
+
+    #[test]
+    fn unknown_and_reserved_options_are_rejected() {
+        let err = args("colour = \"red\"").unwrap_err().to_string();
+        assert!(err.contains("unknown option `colour`"), "{err}");
+        let err = args("input = \"a.tif\"").unwrap_err().to_string();
+        assert!(err.contains("unknown option `input`"), "{err}");
+    }
+
+    #[test]
+    fn switches_need_true() {
+        let err = args("overwrite = \"yes\"").unwrap_err().to_string();
+        assert!(err.contains("is a switch"), "{err}");
+        let err = args("verify = false").unwrap_err().to_string();
+        assert!(err.contains("can only turn on"), "{err}");
+    }
+
+    #[test]
+    fn tables_are_not_values() {
+        assert!(args("[intent]\nx = 1").is_err());
+    }
+}
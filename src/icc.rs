@@ -0,0 +1,530 @@
+// ---------------- Synthesized ICC profiles ----------------
+//
+// lcms2 always adapts the primaries of a synthesized matrix/TRC profile to the
+// D50 PCS with Bradford. To honour `--chromatic-adaptation`, the colorant and
+// `chad` tags are recomputed here with the chosen transform.
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use lcms2::{
+    CIEXYZ, CIExyY, CIExyYTRIPLE, ColorSpaceSignature, Flags, Intent, Locale, MLU, PixelFormat, Profile,
+    ProfileClassSignature, Tag, TagSignature, ToneCurve, Transform,
+};
+use std::path::Path;
+
+type Mat3 = [[f64; 3]; 3];
+
+/// lcms2 D50 PCS white (ICC.1 Annex A, s15Fixed16-rounded).
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// Chromatic adaptation transform used to bring synthesized profiles to D50.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ChromaticAdaptation {
+    /// Bradford (ICC recommendation, lcms2 default)
+    Bradford,
+    /// von Kries with Hunt-Pointer-Estevez cone responses
+    VonKries,
+    /// CIECAM02 CAT02
+    Cat02,
+    /// Plain XYZ scaling (wrong von Kries)
+    XyzScaling,
+}
+
+impl ChromaticAdaptation {
+    /// Command-line name, for logs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bradford => "bradford",
+            Self::VonKries => "von-kries",
+            Self::Cat02 => "cat02",
+            Self::XyzScaling => "xyz-scaling",
+        }
+    }
+
+    /// XYZ -> cone response matrix.
+    fn cone_matrix(self) -> Mat3 {
+        match self {
+            Self::Bradford => [
+                [0.8951, 0.2664, -0.1614],
+                [-0.7502, 1.7135, 0.0367],
+                [0.0389, -0.0685, 1.0296],
+            ],
+            Self::VonKries => [
+                [0.40024, 0.70760, -0.08081],
+                [-0.22630, 1.16532, 0.04570],
+                [0.0, 0.0, 0.91822],
+            ],
+            Self::Cat02 => [
+                [0.7328, 0.4296, -0.1624],
+                [-0.7036, 1.6975, 0.0061],
+                [0.0030, 0.0136, 0.9834],
+            ],
+            Self::XyzScaling => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// Matrix adapting XYZ under white `src` to XYZ under white `dst`.
+    fn adaptation_matrix(self, src: [f64; 3], dst: [f64; 3]) -> Mat3 {
+        let cone = self.cone_matrix();
+        let s = mul_vec(&cone, src);
+        let d = mul_vec(&cone, dst);
+        let scale = [
+            [d[0] / s[0], 0.0, 0.0],
+            [0.0, d[1] / s[1], 0.0],
+            [0.0, 0.0, d[2] / s[2]],
+        ];
+        mul(&invert(&cone), &mul(&scale, &cone))
+    }
+}
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn mul_vec(m: &Mat3, v: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2])
+}
+
+fn invert(m: &Mat3) -> Mat3 {
+    let cof = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adj = [
+        [cof(1, 2, 1, 2), -cof(0, 2, 1, 2), cof(0, 1, 1, 2)],
+        [-cof(1, 2, 0, 2), cof(0, 2, 0, 2), -cof(0, 1, 0, 2)],
+        [cof(1, 2, 0, 1), -cof(0, 2, 0, 1), cof(0, 1, 0, 1)],
+    ];
+    let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+    adj.map(|row| row.map(|v| v / det))
+}
+
+fn xyy_to_xyz(c: &CIExyY) -> [f64; 3] {
+    [c.x / c.y * c.Y, c.Y, (1.0 - c.x - c.y) / c.y * c.Y]
+}
+
+/// Matrix/TRC RGB profile with colorants adapted to D50 by `cat`.
+pub fn synthesize_rgb(
+    description: &str,
+    white: &CIExyY,
+    primaries: &CIExyYTRIPLE,
+    trc: &ToneCurve,
+    cat: ChromaticAdaptation,
+) -> Result<Profile> {
+    // lcms2 fills header, white point, chromaticity and TRC tags...
+    let mut prof = Profile::new_rgb(white, primaries, &[trc, trc, trc])
+        .with_context(|| format!("Create RGB profile '{description}'"))?;
+
+    // ...then the colorants are replaced with our own adaptation.
+    let wp = xyy_to_xyz(white);
+    let prim = [&primaries.Red, &primaries.Green, &primaries.Blue].map(xyy_to_xyz);
+    let p = [
+        [prim[0][0], prim[1][0], prim[2][0]],
+        [prim[0][1], prim[1][1], prim[2][1]],
+        [prim[0][2], prim[1][2], prim[2][2]],
+    ];
+    let s = mul_vec(&invert(&p), wp);
+    let rgb_to_xyz = [0, 1, 2].map(|i| [0, 1, 2].map(|j| p[i][j] * s[j]));
+    let chad = cat.adaptation_matrix(wp, D50);
+    let m = mul(&chad, &rgb_to_xyz);
+
+    let colorants = [
+        TagSignature::RedColorantTag,
+        TagSignature::GreenColorantTag,
+        TagSignature::BlueColorantTag,
+    ];
+    for (j, sig) in colorants.into_iter().enumerate() {
+        let xyz = CIEXYZ { X: m[0][j], Y: m[1][j], Z: m[2][j] };
+        anyhow::ensure!(prof.write_tag(sig, Tag::CIEXYZ(&xyz)), "Write {sig:?}");
+    }
+
+    write_chad(&mut prof, &chad)?;
+    set_description(&mut prof, description)?;
+    Ok(prof)
+}
+
+/// `chad` is a 3x3 s15Fixed16 matrix; lcms2 stores it as a cmsMAT3, which
+/// has the same layout as a CIExyYTRIPLE (three rows of three f64).
+fn write_chad(prof: &mut Profile, chad: &Mat3) -> Result<()> {
+    let row = |r: [f64; 3]| CIExyY { x: r[0], y: r[1], Y: r[2] };
+    let chad_tag = CIExyYTRIPLE { Red: row(chad[0]), Green: row(chad[1]), Blue: row(chad[2]) };
+    anyhow::ensure!(
+        prof.write_tag(TagSignature::ChromaticAdaptationTag, Tag::CIExyYTRIPLE(&chad_tag)),
+        "Write chad tag"
+    );
+    Ok(())
+}
+
+fn set_description(prof: &mut Profile, description: &str) -> Result<()> {
+    let mut desc = MLU::new(1);
+    desc.set_text_ascii(description, Locale::none());
+    anyhow::ensure!(
+        prof.write_tag(TagSignature::ProfileDescriptionTag, Tag::MLU(&desc)),
+        "Write profile description"
+    );
+    Ok(())
+}
+
+// ---------------- Built-in named profiles ----------------
+
+/// Prefix selecting a built-in profile instead of a file, e.g. `builtin:ecirgb-v2`.
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+/// Names accepted after `builtin:`.
+pub const BUILTIN_NAMES: &[&str] = &[
+    "srgb",
+    "ecirgb-v2",
+    "adobe-rgb-1998",
+    "prophoto",
+    "display-p3",
+    "gray-gamma-2.2",
+    "gray-srgb",
+];
+
+const D50_XYY: CIExyY = CIExyY { x: 0.3457, y: 0.3585, Y: 1.0 };
+const D65_XYY: CIExyY = CIExyY { x: 0.3127, y: 0.3290, Y: 1.0 };
+
+const fn primaries(r: (f64, f64), g: (f64, f64), b: (f64, f64)) -> CIExyYTRIPLE {
+    CIExyYTRIPLE {
+        Red: CIExyY { x: r.0, y: r.1, Y: 1.0 },
+        Green: CIExyY { x: g.0, y: g.1, Y: 1.0 },
+        Blue: CIExyY { x: b.0, y: b.1, Y: 1.0 },
+    }
+}
+
+/// IEC 61966-2-1 sRGB curve (also used by Display P3).
+fn srgb_trc() -> Result<ToneCurve> {
+    Ok(ToneCurve::new_parametric(4, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])?)
+}
+
+/// Built-in sRGB. Bradford yields lcms2's own `cmsCreate_sRGBProfile`.
+pub fn srgb(cat: ChromaticAdaptation) -> Result<Profile> {
+    if cat == ChromaticAdaptation::Bradford {
+        return Ok(Profile::new_srgb());
+    }
+    let prim = primaries((0.6400, 0.3300), (0.3000, 0.6000), (0.1500, 0.0600));
+    synthesize_rgb("sRGB built-in", &D65_XYY, &prim, &srgb_trc()?, cat)
+}
+
+/// Synthesize a built-in profile by name (without the `builtin:` prefix).
+pub fn builtin(name: &str, cat: ChromaticAdaptation) -> Result<Profile> {
+    match name {
+        "srgb" => srgb(cat),
+        "ecirgb-v2" => {
+            // eciRGB v2: D50, L* TRC
+            let prim = primaries((0.6700, 0.3300), (0.2100, 0.7100), (0.1400, 0.0800));
+            let trc = ToneCurve::new_parametric(4, &[3.0, 1.0 / 1.16, 0.16 / 1.16, 100.0 / 903.3, 0.08])?;
+            synthesize_rgb("eciRGB v2 built-in", &D50_XYY, &prim, &trc, cat)
+        }
+        "adobe-rgb-1998" => {
+            let prim = primaries((0.6400, 0.3300), (0.2100, 0.7100), (0.1500, 0.0600));
+            let trc = ToneCurve::new(563.0 / 256.0);
+            synthesize_rgb("Adobe RGB (1998) compatible built-in", &D65_XYY, &prim, &trc, cat)
+        }
+        "prophoto" => {
+            // ROMM RGB (ISO 22028-2): gamma 1.8 with a linear segment below 1/512
+            let prim = primaries((0.7347, 0.2653), (0.1596, 0.8404), (0.0366, 0.0001));
+            let trc = ToneCurve::new_parametric(4, &[1.8, 1.0, 0.0, 1.0 / 16.0, 16.0 / 512.0])?;
+            synthesize_rgb("ProPhoto RGB (ROMM) built-in", &D50_XYY, &prim, &trc, cat)
+        }
+        "display-p3" => {
+            let prim = primaries((0.6800, 0.3200), (0.2650, 0.6900), (0.1500, 0.0600));
+            synthesize_rgb("Display P3 built-in", &D65_XYY, &prim, &srgb_trc()?, cat)
+        }
+        "gray-gamma-2.2" => {
+            let trc = ToneCurve::new(2.2);
+            let mut prof = Profile::new_gray(&D50_XYY, &trc).context("Create gray profile")?;
+            set_description(&mut prof, "Gray Gamma 2.2 built-in")?;
+            Ok(prof)
+        }
+        "gray-srgb" => {
+            // Gray with the sRGB curve (JPEG 2000 enumerated greyscale)
+            let mut prof = Profile::new_gray(&D50_XYY, &srgb_trc()?).context("Create gray profile")?;
+            set_description(&mut prof, "Gray sRGB TRC built-in")?;
+            Ok(prof)
+        }
+        _ => bail!(
+            "Unknown built-in profile '{name}'. Available: {}",
+            BUILTIN_NAMES.join(", ")
+        ),
+    }
+}
+
+/// Load an ICC profile from a file, or synthesize it for `builtin:<name>`.
+pub fn load_profile(path: &Path, cat: ChromaticAdaptation) -> Result<Profile> {
+    if let Some(name) = path.to_str().and_then(|p| p.strip_prefix(BUILTIN_PREFIX)) {
+        return builtin(&name.to_ascii_lowercase(), cat);
+    }
+    Profile::new_file(path).with_context(|| format!("Read ICC profile {}", path.display()))
+}
+
+// ---------------- ICC version policy ----------------
+
+/// ICC version of the output profile written to files (embedded or sidecar).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum IccVersion {
+    /// ICC v2 (2.4), for JPEG 2000 tools and viewers that reject v4
+    V2,
+    /// ICC v4 (4.3)
+    V4,
+    /// Write the profile in its own version
+    Keep,
+}
+
+/// Serialize `prof` in the requested ICC version.
+///
+/// Matrix/TRC profiles are rebuilt tag by tag on a fresh profile, so lcms2
+/// writes the tag types of the target version (`desc`/`curv` for v2,
+/// `mluc`/`para` for v4) and the white point convention of that version.
+/// LUT-based profiles cannot be converted and are rejected.
+pub fn serialize_as(prof: &Profile, version: IccVersion) -> Result<Vec<u8>> {
+    let target: f64 = match version {
+        IccVersion::Keep => return Ok(prof.icc()?),
+        IccVersion::V2 => 2.4,
+        IccVersion::V4 => 4.3,
+    };
+    if prof.version().trunc() == target.trunc() {
+        return Ok(prof.icc()?);
+    }
+
+    let name = prof
+        .info(lcms2::InfoType::Description, Locale::none())
+        .unwrap_or_else(|| "(no description)".into());
+    let gray = prof.color_space() == ColorSpaceSignature::GrayData;
+    if !gray && !prof.is_matrix_shaper() {
+        bail!(
+            "Output profile '{}' (ICC v{:.1}) is LUT-based and cannot be re-serialized as ICC v{}; \
+             use a matrix/TRC profile or --icc-version keep",
+            name.trim(),
+            prof.version(),
+            target.trunc()
+        );
+    }
+
+    let mut out = Profile::new_placeholder();
+    out.set_device_class(prof.device_class());
+    out.set_color_space(prof.color_space());
+    out.set_pcs(ColorSpaceSignature::XYZData);
+    out.set_version(target);
+
+    for sig in [TagSignature::ProfileDescriptionTag, TagSignature::CopyrightTag] {
+        if let tag @ Tag::MLU(_) = prof.read_tag(sig) {
+            anyhow::ensure!(out.write_tag(sig, tag), "Write {sig:?}");
+        }
+    }
+
+    let copied: &[TagSignature] = if gray {
+        &[TagSignature::GrayTRCTag]
+    } else {
+        &[
+            TagSignature::RedColorantTag,
+            TagSignature::GreenColorantTag,
+            TagSignature::BlueColorantTag,
+            TagSignature::RedTRCTag,
+            TagSignature::GreenTRCTag,
+            TagSignature::BlueTRCTag,
+        ]
+    };
+    for &sig in copied {
+        let tag = prof.read_tag(sig);
+        anyhow::ensure!(
+            !matches!(tag, Tag::None) && out.write_tag(sig, tag),
+            "Profile '{}' has no usable {sig:?}",
+            name.trim()
+        );
+    }
+
+    // v4 stores D50 as media white plus a `chad` from the actual white; v2
+    // stores the actual white.
+    let media = media_white(prof);
+    if target < 4.0 {
+        let wp = CIEXYZ { X: media[0], Y: media[1], Z: media[2] };
+        anyhow::ensure!(out.write_tag(TagSignature::MediaWhitePointTag, Tag::CIEXYZ(&wp)), "Write wtpt");
+    } else {
+        let d50 = CIEXYZ { X: D50[0], Y: D50[1], Z: D50[2] };
+        anyhow::ensure!(out.write_tag(TagSignature::MediaWhitePointTag, Tag::CIEXYZ(&d50)), "Write wtpt");
+        if media.iter().zip(D50).any(|(a, b)| (a - b).abs() > 1e-4) {
+            write_chad(&mut out, &ChromaticAdaptation::Bradford.adaptation_matrix(media, D50))?;
+        }
+    }
+
+    Ok(out.icc()?)
+}
+
+/// Actual media white of a profile: `wtpt`, undone through `chad` for v4.
+fn media_white(prof: &Profile) -> [f64; 3] {
+    let wtpt = match prof.read_tag(TagSignature::MediaWhitePointTag) {
+        Tag::CIEXYZ(xyz) => [xyz.X, xyz.Y, xyz.Z],
+        _ => D50,
+    };
+    if prof.version() < 4.0 {
+        return wtpt;
+    }
+    match prof.read_tag(TagSignature::ChromaticAdaptationTag) {
+        Tag::CIExyYTRIPLE(m) => {
+            let chad = [m.Red, m.Green, m.Blue].map(|r| [r.x, r.y, r.Y]);
+            mul_vec(&invert(&chad), wtpt)
+        }
+        _ => wtpt,
+    }
+}
+
+// ---------------- JP2 restricted ICC ----------------
+
+/// Reasons why `prof` is not a JP2 restricted ICC profile (`colr` method 2:
+/// monochrome or three-component matrix/TRC input or display profile with an
+/// XYZ PCS). Empty when the profile qualifies.
+pub fn jp2_restricted_issues(prof: &Profile) -> Vec<String> {
+    let mut issues = Vec::new();
+    if !matches!(
+        prof.device_class(),
+        ProfileClassSignature::InputClass | ProfileClassSignature::DisplayClass
+    ) {
+        issues.push(format!("profile class {:?} (must be input or display)", prof.device_class()));
+    }
+    if !matches!(prof.color_space(), ColorSpaceSignature::RgbData | ColorSpaceSignature::GrayData) {
+        issues.push(format!("colour space {:?} (must be RGB or gray)", prof.color_space()));
+    }
+    if prof.pcs() != ColorSpaceSignature::XYZData {
+        issues.push(format!("PCS {:?} (must be XYZ)", prof.pcs()));
+    }
+    if !prof.is_matrix_shaper() {
+        issues.push("LUT-based (must be matrix/TRC)".into());
+    }
+    issues
+}
+
+/// Entries of the tabulated TRCs of an approximation.
+const APPROX_TRC_POINTS: usize = 1024;
+
+/// Matrix/TRC approximation of an RGB profile (ICC v2.4, input class), for
+/// JP2 restricted ICC.
+///
+/// The colorants are the PCS values of the three primaries, scaled so that
+/// RGB (1,1,1) hits the profile's white; the TRCs follow the neutral axis
+/// through the inverse of that matrix.
+pub fn matrix_trc_approximation(prof: &Profile) -> Result<Profile> {
+    anyhow::ensure!(
+        prof.color_space() == ColorSpaceSignature::RgbData,
+        "Only RGB profiles can be approximated by matrix/TRC"
+    );
+
+    let to_xyz = RgbToXyz::new(prof)?;
+    let m = to_xyz.colorant_matrix();
+    let inv = invert(&m);
+
+    let mut tables = [[0u16; APPROX_TRC_POINTS]; 3];
+    let mut prev = [0.0f64; 3];
+    for n in 0..APPROX_TRC_POINTS {
+        let v = n as f64 / (APPROX_TRC_POINTS - 1) as f64;
+        let lin = mul_vec(&inv, to_xyz.eval([v, v, v]));
+        for ((table, prev), lin) in tables.iter_mut().zip(&mut prev).zip(lin) {
+            // Monotonic, 0..1, exact at the white end
+            let y = if n == APPROX_TRC_POINTS - 1 { 1.0 } else { lin.clamp(*prev, 1.0) };
+            *prev = y;
+            table[n] = (y * 65535.0).round() as u16;
+        }
+    }
+
+    let name = prof
+        .info(lcms2::InfoType::Description, Locale::none())
+        .unwrap_or_else(|| "profile".into());
+    let trcs = tables.map(|t| ToneCurve::new_tabulated(&t));
+    matrix_trc_profile(
+        &format!("{} (matrix/TRC approximation)", name.trim()),
+        media_white(prof),
+        &m,
+        [&trcs[0], &trcs[1], &trcs[2]],
+    )
+}
+
+/// lcms2 `LCMS_USED_AS_OUTPUT`.
+const USED_AS_OUTPUT: u32 = 1;
+
+/// Whether `prof` can be the output of a transform with `intent`: matrix/TRC,
+/// or BToA tables (LUT-based input profiles often only have AToB).
+pub fn usable_as_output(prof: &Profile, intent: Intent) -> bool {
+    prof.is_intent_supported(intent, USED_AS_OUTPUT)
+}
+
+/// Linear-light version of a working space, for resampling: the colorants
+/// of `prof` (or of its matrix/TRC approximation) with gamma 1.0 TRCs.
+///
+/// Gray profiles get the sRGB colorants; only their neutral axis is used.
+pub fn linear_rgb(prof: &Profile) -> Result<Profile> {
+    let m = if prof.color_space() == ColorSpaceSignature::GrayData {
+        RgbToXyz::new(&Profile::new_srgb())?.colorant_matrix()
+    } else {
+        anyhow::ensure!(
+            prof.color_space() == ColorSpaceSignature::RgbData,
+            "Only RGB and gray profiles have a linear-light working space"
+        );
+        RgbToXyz::new(prof)?.colorant_matrix()
+    };
+    let linear = ToneCurve::new(1.0);
+    matrix_trc_profile("Linear light working space", media_white(prof), &m, [&linear, &linear, &linear])
+}
+
+/// RGB -> PCS XYZ of a profile (relative colorimetric, unoptimized).
+struct RgbToXyz(Transform<[f64; 3], [f64; 3]>);
+
+impl RgbToXyz {
+    fn new(prof: &Profile) -> Result<Self> {
+        let xyz = Profile::new_xyz();
+        let xform = Transform::new_flags(
+            prof,
+            PixelFormat::RGB_DBL,
+            &xyz,
+            PixelFormat::XYZ_DBL,
+            Intent::RelativeColorimetric,
+            Flags::NO_OPTIMIZE,
+        )
+        .context("Create RGB -> XYZ transform")?;
+        Ok(Self(xform))
+    }
+
+    fn eval(&self, rgb: [f64; 3]) -> [f64; 3] {
+        let mut out = [[0.0; 3]];
+        self.0.transform_pixels(&[rgb], &mut out);
+        out[0]
+    }
+
+    /// PCS values of the three primaries (columns), scaled so that
+    /// RGB (1,1,1) hits the profile's white.
+    fn colorant_matrix(&self) -> Mat3 {
+        let prim = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(|c| self.eval(c));
+        let m = [0, 1, 2].map(|i| [prim[0][i], prim[1][i], prim[2][i]]);
+        let s = mul_vec(&invert(&m), self.eval([1.0, 1.0, 1.0]));
+        [0, 1, 2].map(|i| [0, 1, 2].map(|j| m[i][j] * s[j]))
+    }
+}
+
+/// ICC v2.4 input-class matrix/TRC RGB profile.
+fn matrix_trc_profile(description: &str, media: [f64; 3], m: &Mat3, trcs: [&ToneCurve; 3]) -> Result<Profile> {
+    let mut out = Profile::new_placeholder();
+    out.set_device_class(ProfileClassSignature::InputClass);
+    out.set_color_space(ColorSpaceSignature::RgbData);
+    out.set_pcs(ColorSpaceSignature::XYZData);
+    out.set_version(2.4);
+    set_description(&mut out, description)?;
+
+    let wp = CIEXYZ { X: media[0], Y: media[1], Z: media[2] };
+    anyhow::ensure!(out.write_tag(TagSignature::MediaWhitePointTag, Tag::CIEXYZ(&wp)), "Write wtpt");
+
+    let colorants = [
+        (TagSignature::RedColorantTag, TagSignature::RedTRCTag),
+        (TagSignature::GreenColorantTag, TagSignature::GreenTRCTag),
+        (TagSignature::BlueColorantTag, TagSignature::BlueTRCTag),
+    ];
+    for (c, (xyz_sig, trc_sig)) in colorants.into_iter().enumerate() {
+        let col = CIEXYZ { X: m[0][c], Y: m[1][c], Z: m[2][c] };
+        anyhow::ensure!(out.write_tag(xyz_sig, Tag::CIEXYZ(&col)), "Write {xyz_sig:?}");
+        anyhow::ensure!(out.write_tag(trc_sig, Tag::ToneCurve(trcs[c])), "Write {trc_sig:?}");
+    }
+
+    // Round-trip through lcms2 so the result is a plain, fully serialized profile.
+    Ok(Profile::new_icc(&out.icc()?)?)
+}
@@ -0,0 +1,804 @@
+// ---------------- JPEG 2000 (JP2/JPX) input and output ----------------
+//
+// JP2 outputs are encoded in-process with the bundled OpenJPEG library, tile
+// by tile straight from the RGB buffer, with the NDK parameter sets below.
+// NCLR writes the JP2 boxes itself, so the `colr` box carries the output ICC
+// profile (method 2, restricted ICC) and the `res ` box the capture resolution.
+//
+// Inputs are decoded in-process as well, to the raw component samples: the
+// library applies no colour management, so the embedded ICC profile is only
+// applied once, by NCLR. Colour specification and resolution are read from
+// the JP2 header by NCLR.
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::{DynamicImage, ImageBuffer, Primitive};
+use openjpeg_sys as opj;
+use rgb::RGB;
+use std::ffi::{c_char, c_void, CStr};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Compression ratios of the 12 quality layers of the lossy parameter set
+/// (most compressed layer first).
+const LOSSY_RATES: [f32; 12] = [362.0, 256.0, 181.0, 128.0, 90.0, 64.0, 45.0, 32.0, 22.0, 16.0, 11.0, 8.0];
+
+/// Decomposition levels (resolutions - 1) for images large enough.
+const MAX_LEVELS: u32 = 5;
+
+/// JP2 signature box.
+const SIGNATURE: [u8; 12] = [0, 0, 0, 12, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+
+/// NDK JPEG 2000 parameter set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Jp2Params {
+    /// Master Copy: reversible 5-3 wavelet, lossless, single layer, 4096×4096 tiles
+    Lossless,
+    /// User Copy: irreversible 9-7 wavelet, 12 layers with target rates, 1024×1024 tiles
+    Lossy,
+}
+
+impl Jp2Params {
+    fn tile_size(self) -> u32 {
+        match self {
+            Jp2Params::Lossless => 4096,
+            Jp2Params::Lossy => 1024,
+        }
+    }
+
+    /// OpenJPEG encoder parameters for a `w`×`h` image.
+    fn encoder_parameters(self, w: u32, h: u32) -> opj::opj_cparameters_t {
+        // SAFETY: plain C struct, filled in by OpenJPEG
+        let mut p: opj::opj_cparameters_t = unsafe { std::mem::zeroed() };
+        unsafe { opj::opj_set_default_encoder_parameters(&mut p) };
+
+        // Every resolution must keep at least one pixel
+        let levels = MAX_LEVELS.min(w.min(h).max(1).ilog2());
+        p.numresolution = levels as i32 + 1;
+        p.cblockw_init = 64;
+        p.cblockh_init = 64;
+        // Precincts (highest resolution first): 256×256 for the two highest, 128×128 below
+        p.res_spec = levels as i32 + 1;
+        for r in 0..=levels as usize {
+            let size = if r < 2 { 256 } else { 128 };
+            p.prcw_init[r] = size;
+            p.prch_init[r] = size;
+        }
+        // 0x01 user-defined precincts, 0x02 SOP, 0x04 EPH markers
+        p.csty |= 0x01 | 0x02 | 0x04;
+        p.tile_size_on = 1;
+        p.cp_tdx = self.tile_size() as i32;
+        p.cp_tdy = self.tile_size() as i32;
+        p.prog_order = opj::PROG_ORDER::OPJ_RPCL;
+        // A tile-part per resolution
+        p.tp_on = 1;
+        p.tp_flag = b'R' as c_char;
+        // RGB -> YCC component transform
+        p.tcp_mct = 1;
+        p.cp_disto_alloc = 1;
+        match self {
+            Jp2Params::Lossless => {
+                p.tcp_numlayers = 1;
+                p.tcp_rates[0] = 0.0;
+            }
+            Jp2Params::Lossy => {
+                p.irreversible = 1;
+                // Selective arithmetic coding bypass
+                p.mode = 1;
+                p.tcp_numlayers = LOSSY_RATES.len() as i32;
+                p.tcp_rates[..LOSSY_RATES.len()].copy_from_slice(&LOSSY_RATES);
+            }
+        }
+        p
+    }
+}
+
+/// Component sample types of JP2 outputs.
+pub trait Sample: Copy {
+    const BITS: u32;
+    /// Append the sample as `opj_write_tile` reads it (native byte order).
+    fn push(self, out: &mut Vec<u8>);
+    /// `v` of a `prec`-bit component, scaled to the full range of the type.
+    fn from_raw(v: u32, prec: u32) -> Self;
+}
+
+impl Sample for u8 {
+    const BITS: u32 = 8;
+    fn push(self, out: &mut Vec<u8>) {
+        out.push(self);
+    }
+    fn from_raw(v: u32, prec: u32) -> Self {
+        scale_raw(v, prec, Self::BITS) as u8
+    }
+}
+
+impl Sample for u16 {
+    const BITS: u32 = 16;
+    fn push(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_ne_bytes());
+    }
+    fn from_raw(v: u32, prec: u32) -> Self {
+        scale_raw(v, prec, Self::BITS) as u16
+    }
+}
+
+fn scale_raw(v: u32, prec: u32, bits: u32) -> u32 {
+    if prec == bits {
+        return v;
+    }
+    let (from, to) = ((1u64 << prec) - 1, (1u64 << bits) - 1);
+    ((v as u64 * to + from / 2) / from) as u32
+}
+
+/// Encode the `w`×`h` RGB image `pix` (row by row) to the JP2 file `output`,
+/// with `icc` in the `colr` box (enumerated sRGB when `None`) and the capture
+/// resolution, when known, in the `res ` box. A partial file is removed on error.
+pub fn encode<T: Sample>(
+    output: &Path,
+    w: u32,
+    h: u32,
+    pix: &[RGB<T>],
+    params: Jp2Params,
+    icc: Option<&[u8]>,
+    resolution_ppm: Option<(f64, f64)>,
+) -> Result<()> {
+    let file = File::create(output).with_context(|| format!("Create {}", output.display()))?;
+    let result = write_jp2(file, w, h, pix, params, icc, resolution_ppm);
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+fn write_jp2<T: Sample>(
+    mut file: File,
+    w: u32,
+    h: u32,
+    pix: &[RGB<T>],
+    params: Jp2Params,
+    icc: Option<&[u8]>,
+    resolution_ppm: Option<(f64, f64)>,
+) -> Result<()> {
+    let mut head = SIGNATURE.to_vec();
+    // Brand "jp2 ", minor version 0, compatible with "jp2 "
+    write_box(&mut head, b"ftyp", b"jp2 \0\0\0\0jp2 ")?;
+    write_box(&mut head, b"jp2h", &jp2h(w, h, T::BITS, icc, resolution_ppm)?)?;
+    file.write_all(&head)?;
+
+    // The codestream box length is filled in afterwards; a codestream that may
+    // not fit a 32-bit length gets the extended (XLBox) header.
+    let raw_len = w as u64 * h as u64 * 3 * (T::BITS as u64 / 8);
+    let extended = raw_len > u64::from(u32::MAX / 2);
+    let header_len: u64 = if extended { 16 } else { 8 };
+    let box_start = head.len() as u64;
+    file.write_all(&vec![0u8; header_len as usize])?;
+
+    encode_codestream(&mut file, box_start + header_len, w, h, pix, params)?;
+
+    let box_len = file.seek(SeekFrom::End(0))? - box_start;
+    file.seek(SeekFrom::Start(box_start))?;
+    if extended {
+        file.write_all(&1u32.to_be_bytes())?;
+        file.write_all(b"jp2c")?;
+        file.write_all(&box_len.to_be_bytes())?;
+    } else {
+        let len = u32::try_from(box_len).context("JP2 codestream too large for its box header")?;
+        file.write_all(&len.to_be_bytes())?;
+        file.write_all(b"jp2c")?;
+    }
+    file.sync_all().context("Flush JP2 output")
+}
+
+/// `jp2h` payload: image header, colour specification and resolution.
+fn jp2h(w: u32, h: u32, bits: u32, icc: Option<&[u8]>, resolution_ppm: Option<(f64, f64)>) -> Result<Vec<u8>> {
+    let mut ihdr = Vec::with_capacity(14);
+    ihdr.extend_from_slice(&h.to_be_bytes());
+    ihdr.extend_from_slice(&w.to_be_bytes());
+    ihdr.extend_from_slice(&3u16.to_be_bytes());
+    // BPC (unsigned, bits - 1), compression type 7, colour space known, no IPR
+    ihdr.extend_from_slice(&[(bits - 1) as u8, 7, 0, 0]);
+
+    let mut out = Vec::new();
+    write_box(&mut out, b"ihdr", &ihdr)?;
+    match icc {
+        Some(icc) => write_box(&mut out, b"colr", &colr_icc(icc))?,
+        // Method 1, enumerated sRGB
+        None => write_box(&mut out, b"colr", &[1, 0, 0, 0, 0, 0, 16])?,
+    }
+    if let Some((x_ppm, y_ppm)) = resolution_ppm.filter(|&(x, y)| x > 0.0 && y > 0.0) {
+        write_box(&mut out, b"res ", &res_box(x_ppm, y_ppm)?)?;
+    }
+    Ok(out)
+}
+
+/// OpenJPEG handles, released on drop.
+struct Codec(*mut opj::opj_codec_t);
+struct Stream(*mut opj::opj_stream_t);
+struct Image(*mut opj::opj_image_t);
+
+impl Drop for Codec {
+    fn drop(&mut self) {
+        unsafe { opj::opj_destroy_codec(self.0) }
+    }
+}
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe { opj::opj_stream_destroy(self.0) }
+    }
+}
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe { opj::opj_image_destroy(self.0) }
+    }
+}
+
+/// Output of the OpenJPEG stream: `file` from offset `base` on.
+struct Sink<'a> {
+    file: &'a mut File,
+    base: u64,
+    error: Option<io::Error>,
+}
+
+unsafe extern "C" fn sink_write(buf: *mut c_void, len: usize, user: *mut c_void) -> usize {
+    let sink = unsafe { &mut *(user as *mut Sink) };
+    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+    match sink.file.write_all(data) {
+        Ok(()) => len,
+        Err(e) => {
+            sink.error = Some(e);
+            usize::MAX
+        }
+    }
+}
+
+unsafe extern "C" fn sink_skip(len: i64, user: *mut c_void) -> i64 {
+    let sink = unsafe { &mut *(user as *mut Sink) };
+    match sink.file.seek(SeekFrom::Current(len)) {
+        Ok(_) => len,
+        Err(e) => {
+            sink.error = Some(e);
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn sink_seek(pos: i64, user: *mut c_void) -> i32 {
+    let sink = unsafe { &mut *(user as *mut Sink) };
+    match sink.file.seek(SeekFrom::Start(sink.base + pos as u64)) {
+        Ok(_) => 1,
+        Err(e) => {
+            sink.error = Some(e);
+            0
+        }
+    }
+}
+
+/// `what` of the JPEG 2000 `role` failed, with the I/O error and the library's messages.
+fn codec_error(role: &str, what: &str, errors: &[String], io: Option<io::Error>) -> anyhow::Error {
+    let mut msg = format!("JPEG 2000 {role}: {what} failed");
+    if let Some(e) = io {
+        msg = format!("{msg}: {e}");
+    }
+    if !errors.is_empty() {
+        msg = format!("{msg}: {}", errors.join("; "));
+    }
+    anyhow!(msg)
+}
+
+/// Collects OpenJPEG error messages.
+unsafe extern "C" fn collect_message(msg: *const c_char, user: *mut c_void) {
+    let messages = unsafe { &mut *(user as *mut Vec<String>) };
+    messages.push(unsafe { CStr::from_ptr(msg) }.to_string_lossy().trim().to_string());
+}
+
+/// Write the JPEG 2000 codestream of `pix` to `file` at offset `base`,
+/// handing the RGB buffer to OpenJPEG one tile at a time.
+fn encode_codestream<T: Sample>(
+    file: &mut File,
+    base: u64,
+    w: u32,
+    h: u32,
+    pix: &[RGB<T>],
+    params: Jp2Params,
+) -> Result<()> {
+    let mut errors: Vec<String> = Vec::new();
+    let mut sink = Sink { file, base, error: None };
+    let failed = |what: &str, errors: &[String], sink: &mut Sink| codec_error("encoder", what, errors, sink.error.take());
+
+    let comp = opj::opj_image_cmptparm_t {
+        dx: 1,
+        dy: 1,
+        w,
+        h,
+        x0: 0,
+        y0: 0,
+        prec: T::BITS,
+        bpp: T::BITS,
+        sgnd: 0,
+    };
+    let mut comps = [comp; 3];
+
+    // SAFETY: the handles are released by their guards; `sink` and `errors`
+    // outlive the codec and the stream that point to them.
+    unsafe {
+        let image = Image(opj::opj_image_tile_create(3, comps.as_mut_ptr(), opj::COLOR_SPACE::OPJ_CLRSPC_SRGB));
+        if image.0.is_null() {
+            bail!("JPEG 2000 encoder: cannot create image");
+        }
+        (*image.0).x0 = 0;
+        (*image.0).y0 = 0;
+        (*image.0).x1 = w;
+        (*image.0).y1 = h;
+
+        let codec = Codec(opj::opj_create_compress(opj::CODEC_FORMAT::OPJ_CODEC_J2K));
+        if codec.0.is_null() {
+            bail!("JPEG 2000 encoder: cannot create codec");
+        }
+        opj::opj_set_error_handler(codec.0, Some(collect_message), &mut errors as *mut _ as *mut c_void);
+
+        let mut p = params.encoder_parameters(w, h);
+        if opj::opj_setup_encoder(codec.0, &mut p, image.0) == 0 {
+            return Err(failed("setup", &errors, &mut sink));
+        }
+        let options = [c"TLM=YES".as_ptr(), std::ptr::null()];
+        if opj::opj_encoder_set_extra_options(codec.0, options.as_ptr()) == 0 {
+            return Err(failed("TLM option", &errors, &mut sink));
+        }
+        // Use the threads of the current pool (--threads)
+        opj::opj_codec_set_threads(codec.0, rayon::current_num_threads() as i32);
+
+        let stream = Stream(opj::opj_stream_create(1 << 20, 0));
+        if stream.0.is_null() {
+            bail!("JPEG 2000 encoder: cannot create output stream");
+        }
+        opj::opj_stream_set_user_data(stream.0, &mut sink as *mut _ as *mut c_void, None);
+        opj::opj_stream_set_write_function(stream.0, Some(sink_write));
+        opj::opj_stream_set_skip_function(stream.0, Some(sink_skip));
+        opj::opj_stream_set_seek_function(stream.0, Some(sink_seek));
+
+        if opj::opj_start_compress(codec.0, image.0, stream.0) == 0 {
+            return Err(failed("start", &errors, &mut sink));
+        }
+
+        let ts = params.tile_size();
+        let (cols, rows) = (w.div_ceil(ts), h.div_ceil(ts));
+        let mut data = Vec::new();
+        for (index, (r, c)) in (0..rows).flat_map(|r| (0..cols).map(move |c| (r, c))).enumerate() {
+            let (x0, y0) = (c * ts, r * ts);
+            let (tw, th) = (ts.min(w - x0) as usize, ts.min(h - y0) as usize);
+            // Component planes, one after the other
+            data.clear();
+            for plane in 0..3 {
+                for y in y0 as usize..y0 as usize + th {
+                    let start = y * w as usize + x0 as usize;
+                    for p in &pix[start..start + tw] {
+                        [p.r, p.g, p.b][plane].push(&mut data);
+                    }
+                }
+            }
+            let len = u32::try_from(data.len()).context("JPEG 2000 tile too large")?;
+            if opj::opj_write_tile(codec.0, index as u32, data.as_mut_ptr(), len, stream.0) == 0 {
+                return Err(failed(&format!("tile {index}"), &errors, &mut sink));
+            }
+        }
+
+        if opj::opj_end_compress(codec.0, stream.0) == 0 {
+            return Err(failed("end", &errors, &mut sink));
+        }
+        // Flush the stream buffer before the guards go
+        drop(stream);
+        if let Some(e) = sink.error.take() {
+            return Err(e).context("Write JPEG 2000 codestream");
+        }
+        drop(codec);
+        drop(image);
+    }
+    Ok(())
+}
+
+/// Input of the OpenJPEG stream.
+struct Source {
+    file: BufReader<File>,
+    error: Option<io::Error>,
+}
+
+unsafe extern "C" fn source_read(buf: *mut c_void, len: usize, user: *mut c_void) -> usize {
+    let source = unsafe { &mut *(user as *mut Source) };
+    let data = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, len) };
+    match source.file.read(data) {
+        // End of stream
+        Ok(0) => usize::MAX,
+        Ok(n) => n,
+        Err(e) => {
+            source.error = Some(e);
+            usize::MAX
+        }
+    }
+}
+
+unsafe extern "C" fn source_skip(len: i64, user: *mut c_void) -> i64 {
+    let source = unsafe { &mut *(user as *mut Source) };
+    match source.file.seek_relative(len) {
+        Ok(()) => len,
+        Err(e) => {
+            source.error = Some(e);
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn source_seek(pos: i64, user: *mut c_void) -> i32 {
+    let source = unsafe { &mut *(user as *mut Source) };
+    match source.file.seek(SeekFrom::Start(pos as u64)) {
+        Ok(_) => 1,
+        Err(e) => {
+            source.error = Some(e);
+            0
+        }
+    }
+}
+
+/// Decode a JP2/JPX file (a JP2-compatible JPX) with the bundled OpenJPEG,
+/// tile by tile, to the raw samples: greyscale or RGB, with an alpha channel
+/// when there is one more component. No colour conversion is applied, apart
+/// from sYCC to RGB (`colr` enumerated 18).
+///
+/// Unsupported: palettes (`pclr`), subsampled or signed components,
+/// components of different precision and more than 16 bits per component.
+pub fn decode(path: &Path) -> Result<DynamicImage> {
+    let mut file = BufReader::new(File::open(path).with_context(|| format!("Open {}", path.display()))?);
+    if split_boxes(&read_jp2h(&mut file, path)?)?.iter().any(|(k, _)| k == b"pclr") {
+        bail!("{}: palette JP2 (pclr box) is not supported", path.display());
+    }
+    file.rewind()?;
+    let len = file.get_ref().metadata()?.len();
+
+    let mut errors: Vec<String> = Vec::new();
+    let mut source = Source { file, error: None };
+    let failed = |what: &str, errors: &[String], source: &mut Source| codec_error("decoder", what, errors, source.error.take());
+
+    // SAFETY: the handles are released by their guards; `source` and `errors`
+    // outlive the codec and the stream that point to them.
+    unsafe {
+        let codec = Codec(opj::opj_create_decompress(opj::CODEC_FORMAT::OPJ_CODEC_JP2));
+        if codec.0.is_null() {
+            bail!("JPEG 2000 decoder: cannot create codec");
+        }
+        opj::opj_set_error_handler(codec.0, Some(collect_message), &mut errors as *mut _ as *mut c_void);
+        let mut p: opj::opj_dparameters_t = std::mem::zeroed();
+        opj::opj_set_default_decoder_parameters(&mut p);
+        if opj::opj_setup_decoder(codec.0, &mut p) == 0 {
+            return Err(failed("setup", &errors, &mut source));
+        }
+        opj::opj_codec_set_threads(codec.0, rayon::current_num_threads() as i32);
+
+        let stream = Stream(opj::opj_stream_create(1 << 20, 1));
+        if stream.0.is_null() {
+            bail!("JPEG 2000 decoder: cannot create input stream");
+        }
+        opj::opj_stream_set_user_data(stream.0, &mut source as *mut _ as *mut c_void, None);
+        opj::opj_stream_set_user_data_length(stream.0, len);
+        opj::opj_stream_set_read_function(stream.0, Some(source_read));
+        opj::opj_stream_set_skip_function(stream.0, Some(source_skip));
+        opj::opj_stream_set_seek_function(stream.0, Some(source_seek));
+
+        let mut image = Image(std::ptr::null_mut());
+        if opj::opj_read_header(stream.0, codec.0, &mut image.0) == 0 || image.0.is_null() {
+            return Err(failed("header", &errors, &mut source));
+        }
+        let img = &*image.0;
+        let comps = std::slice::from_raw_parts(img.comps, img.numcomps as usize);
+        let prec = comps.first().map_or(0, |c| c.prec);
+        if comps.is_empty() || comps.len() > 4 {
+            bail!("{}: {} components (supported: 1 to 4)", path.display(), comps.len());
+        }
+        if let Some(c) = comps.iter().find(|c| c.dx != 1 || c.dy != 1) {
+            bail!("{}: subsampled components ({}x{}) are not supported", path.display(), c.dx, c.dy);
+        }
+        if comps.iter().any(|c| c.sgnd != 0 || c.prec != prec) || !(1..=16).contains(&prec) {
+            bail!(
+                "{}: only unsigned components of equal precision up to 16 bits are supported",
+                path.display()
+            );
+        }
+        let sycc = match img.color_space {
+            opj::COLOR_SPACE::OPJ_CLRSPC_SYCC if comps.len() >= 3 => true,
+            opj::COLOR_SPACE::OPJ_CLRSPC_SYCC | opj::COLOR_SPACE::OPJ_CLRSPC_EYCC | opj::COLOR_SPACE::OPJ_CLRSPC_CMYK => {
+                bail!("{}: colour space {:?} is not supported", path.display(), img.color_space)
+            }
+            _ => false,
+        };
+        let tiles = Tiles { origin: (img.x0, img.y0), w: img.x1 - img.x0, h: img.y1 - img.y0, n: comps.len(), prec, sycc };
+
+        let (w, h) = (tiles.w, tiles.h);
+        let decoded = match (comps.len(), prec <= 8) {
+            (1, true) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLuma8)),
+            (2, true) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLumaA8)),
+            (3, true) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgb8)),
+            (_, true) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgba8)),
+            (1, false) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLuma16)),
+            (2, false) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageLumaA16)),
+            (3, false) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgb16)),
+            (_, false) => tiles.decode(&codec, &stream).map(|d| ImageBuffer::from_raw(w, h, d).map(DynamicImage::ImageRgba16)),
+        };
+        match decoded {
+            Ok(img) => img.context("JPEG 2000 decoder: sample count does not match the image size"),
+            Err(what) => Err(failed(&what, &errors, &mut source)),
+        }
+    }
+}
+
+/// Layout of the image being decoded.
+struct Tiles {
+    /// Image offset on the reference grid
+    origin: (u32, u32),
+    w: u32,
+    h: u32,
+    /// Components per pixel
+    n: usize,
+    /// Bits per component
+    prec: u32,
+    /// Components 0..3 are sYCC
+    sycc: bool,
+}
+
+impl Tiles {
+    /// Decode all tiles into interleaved samples; on failure, the step that failed.
+    unsafe fn decode<T: Sample + Primitive>(&self, codec: &Codec, stream: &Stream) -> std::result::Result<Vec<T>, String> {
+        let mut out = vec![T::from_raw(0, self.prec); self.w as usize * self.h as usize * self.n];
+        let bytes = if self.prec <= 8 { 1 } else { 2 };
+        let mut data = Vec::new();
+        loop {
+            let (mut index, mut size, mut go_on, mut ncomps) = (0u32, 0u32, 0i32, 0u32);
+            let (mut x0, mut y0, mut x1, mut y1) = (0i32, 0i32, 0i32, 0i32);
+            let ok = unsafe {
+                opj::opj_read_tile_header(
+                    codec.0, stream.0, &mut index, &mut size, &mut x0, &mut y0, &mut x1, &mut y1, &mut ncomps, &mut go_on,
+                )
+            };
+            if ok == 0 {
+                return Err("tile header".into());
+            }
+            if go_on == 0 {
+                break;
+            }
+            data.resize(size as usize, 0u8);
+            if unsafe { opj::opj_decode_tile_data(codec.0, index, data.as_mut_ptr(), size, stream.0) } == 0 {
+                return Err(format!("tile {index}"));
+            }
+
+            // Tile component planes, one after the other
+            let (tx, ty) = (x0 as u32 - self.origin.0, y0 as u32 - self.origin.1);
+            let (tw, th) = ((x1 - x0) as usize, (y1 - y0) as usize);
+            let plane = tw * th;
+            let sample = |c: usize, i: usize| -> u32 {
+                let at = (c * plane + i) * bytes;
+                if bytes == 1 {
+                    data[at] as u32
+                } else {
+                    u16::from_ne_bytes([data[at], data[at + 1]]) as u32
+                }
+            };
+            for y in 0..th {
+                let row = ((ty as usize + y) * self.w as usize + tx as usize) * self.n;
+                for x in 0..tw {
+                    let mut v = [0u32; 4];
+                    for (c, v) in v.iter_mut().enumerate().take(self.n) {
+                        *v = sample(c, y * tw + x);
+                    }
+                    if self.sycc {
+                        sycc_to_rgb(&mut v, self.prec);
+                    }
+                    let o = row + x * self.n;
+                    for c in 0..self.n {
+                        out[o + c] = T::from_raw(v[c], self.prec);
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Convert the first three components of `v` from sYCC (`prec` bits) to RGB,
+/// as OpenJPEG's `opj_decompress` does.
+fn sycc_to_rgb(v: &mut [u32; 4], prec: u32) {
+    let offset = (1i32 << (prec - 1)) as f32;
+    let max = ((1i64 << prec) - 1) as f32;
+    let (y, cb, cr) = (v[0] as f32, v[1] as f32 - offset, v[2] as f32 - offset);
+    let rgb = [y + 1.402 * cr, y - 0.344 * cb - 0.714 * cr, y + 1.772 * cb];
+    for (v, c) in v.iter_mut().zip(rgb) {
+        *v = c.round().clamp(0.0, max) as u32;
+    }
+}
+
+// ---------------- JP2 boxes ----------------
+
+struct BoxHeader {
+    kind: [u8; 4],
+    /// Offset of the box payload in the file.
+    payload: u64,
+    /// Payload length; `None` when the box extends to the end of the file.
+    len: Option<u64>,
+}
+
+fn read_box_header<R: Read + Seek>(r: &mut R) -> Result<Option<BoxHeader>> {
+    let start = r.stream_position()?;
+    let mut head = [0u8; 8];
+    match r.read_exact(&mut head) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let lbox = u32::from_be_bytes(head[0..4].try_into().unwrap()) as u64;
+    let kind: [u8; 4] = head[4..8].try_into().unwrap();
+    let (header_len, total) = match lbox {
+        0 => (8, None),
+        1 => {
+            let mut xl = [0u8; 8];
+            r.read_exact(&mut xl)?;
+            (16, Some(u64::from_be_bytes(xl)))
+        }
+        n => (8, Some(n)),
+    };
+    let len = match total {
+        Some(t) if t < header_len => bail!("Bad JP2 box length {t} at offset {start}"),
+        Some(t) => Some(t - header_len),
+        None => None,
+    };
+    Ok(Some(BoxHeader { kind, payload: start + header_len, len }))
+}
+
+fn write_box<W: Write>(w: &mut W, kind: &[u8; 4], payload: &[u8]) -> Result<()> {
+    let len = u32::try_from(payload.len() + 8).context("JP2 header box too large")?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(payload)?;
+    Ok(())
+}
+
+/// Sub-boxes of a superbox payload, as (type, payload).
+fn split_boxes(mut data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = Vec::new();
+    while !data.is_empty() {
+        let mut cur = io::Cursor::new(data);
+        let head = read_box_header(&mut cur)?.context("Truncated JP2 header box")?;
+        let start = head.payload as usize;
+        let end = match head.len {
+            Some(len) => start + len as usize,
+            None => data.len(),
+        };
+        if end > data.len() {
+            bail!("Truncated JP2 header box '{}'", String::from_utf8_lossy(&head.kind));
+        }
+        boxes.push((head.kind, &data[start..end]));
+        data = &data[end..];
+    }
+    Ok(boxes)
+}
+
+/// Colour specification of a JP2/JPX file.
+pub enum Colour {
+    /// ICC profile (method 2 restricted ICC, or JPX method 3 any ICC)
+    Icc(Vec<u8>),
+    /// Enumerated colour space (method 1), e.g. 16 = sRGB, 17 = greyscale
+    Enumerated(u32),
+}
+
+/// Colour and resolution from a JP2/JPX header.
+pub struct Header {
+    pub colour: Option<Colour>,
+    /// Capture resolution (`resc`), else default display resolution (`resd`),
+    /// in pixels per metre (x, y).
+    pub resolution_ppm: Option<(f64, f64)>,
+}
+
+/// Read the colour specification and resolution of a JP2/JPX file.
+///
+/// Of several `colr` boxes the first one with a known method is used.
+pub fn read_header(path: &Path) -> Result<Header> {
+    let mut r = BufReader::new(File::open(path).with_context(|| format!("Open {}", path.display()))?);
+    let jp2h = read_jp2h(&mut r, path)?;
+    let boxes = split_boxes(&jp2h)?;
+    let colour = boxes
+        .iter()
+        .filter(|(k, _)| k == b"colr")
+        .find_map(|(_, p)| match p.first()? {
+            1 => Some(Colour::Enumerated(u32::from_be_bytes(p.get(3..7)?.try_into().ok()?))),
+            2 | 3 => Some(Colour::Icc(p.get(3..)?.to_vec())),
+            _ => None,
+        });
+
+    let mut resolution_ppm = None;
+    if let Some((_, res)) = boxes.iter().find(|(k, _)| k == b"res ") {
+        let sub = split_boxes(res)?;
+        resolution_ppm = [b"resc", b"resd"]
+            .iter()
+            .find_map(|kind| sub.iter().find(|(k, _)| k == *kind))
+            .and_then(|(_, p)| parse_resolution(p));
+    }
+
+    Ok(Header { colour, resolution_ppm })
+}
+
+/// Payload of the JP2 header box (`jp2h`) of `r`.
+fn read_jp2h<R: Read + Seek>(r: &mut R, path: &Path) -> Result<Vec<u8>> {
+    loop {
+        let Some(head) = read_box_header(r)? else {
+            bail!("{} has no JP2 header box (not a JP2/JPX file?)", path.display());
+        };
+        let len = head.len.unwrap_or(0);
+        if &head.kind == b"jp2h" {
+            let mut payload = vec![0u8; len as usize];
+            r.read_exact(&mut payload).context("Read JP2 header box")?;
+            return Ok(payload);
+        }
+        if head.len.is_none() {
+            bail!("{} has no JP2 header box (not a JP2/JPX file?)", path.display());
+        }
+        r.seek(SeekFrom::Start(head.payload + len))?;
+    }
+}
+
+/// (x, y) pixels per metre from a `resc`/`resd` payload.
+fn parse_resolution(p: &[u8]) -> Option<(f64, f64)> {
+    if p.len() < 10 {
+        return None;
+    }
+    let u = |i: usize| u16::from_be_bytes([p[i], p[i + 1]]) as f64;
+    let (vn, vd, hn, hd) = (u(0), u(2), u(4), u(6));
+    let (ve, he) = (p[8] as i8 as i32, p[9] as i8 as i32);
+    if vd == 0.0 || hd == 0.0 {
+        return None;
+    }
+    Some((hn / hd * 10f64.powi(he), vn / vd * 10f64.powi(ve)))
+}
+
+/// `colr` box payload: method 2 (restricted ICC).
+fn colr_icc(icc: &[u8]) -> Vec<u8> {
+    let mut p = vec![2, 0, 0]; // METH, PREC, APPROX
+    p.extend_from_slice(icc);
+    p
+}
+
+/// `res ` superbox holding a capture resolution (`resc`) in pixels per metre.
+fn res_box(x_ppm: f64, y_ppm: f64) -> Result<Vec<u8>> {
+    let (vn, vd, ve) = resolution_fields(y_ppm);
+    let (hn, hd, he) = resolution_fields(x_ppm);
+    let mut resc = Vec::with_capacity(10);
+    for v in [vn, vd, hn, hd] {
+        resc.extend_from_slice(&v.to_be_bytes());
+    }
+    resc.extend_from_slice(&[ve as u8, he as u8]);
+
+    let mut res = Vec::new();
+    write_box(&mut res, b"resc", &resc)?;
+    Ok(res)
+}
+
+/// Closest N / D × 10^E (16-bit N and D) to `ppm`.
+fn resolution_fields(ppm: f64) -> (u16, u16, i8) {
+    let mut best = (1u16, 1u16, 0i8);
+    let mut best_err = f64::INFINITY;
+    for e in -2i8..=4 {
+        let scaled = ppm / 10f64.powi(e as i32);
+        for d in 1..=u16::MAX {
+            let n = (scaled * d as f64).round();
+            if n > u16::MAX as f64 {
+                break;
+            }
+            if n < 1.0 {
+                continue;
+            }
+            let err = (n / d as f64 - scaled).abs() * 10f64.powi(e as i32);
+            if err < best_err {
+                best = (n as u16, d, e);
+                best_err = err;
+                if err == 0.0 {
+                    return best;
+                }
+            }
+        }
+    }
+    best
+}
@@ -11,16 +11,17 @@ License: GPL-3.0-or-later
 */
 
 use anyhow::{anyhow, bail, Context, Result};
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, Parser, ValueEnum};
 use image::GenericImageView;
-use lcms2::{Flags, Intent, PixelFormat, Profile, Transform};
-use rgb::{RGB16, RGB8};
+use lcms2::{ColorSpaceSignature, DisallowCache, Flags, Intent, Profile};
+use rgb::{RGB, RGB16, RGB8};
 use std::borrow::Cow;
 use std::cmp::min;
 use std::fs;
 use std::fs::File;
-use std::io::{BufWriter, Read, Seek, SeekFrom};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use walkdir::WalkDir;
 use rayon::prelude::*;
@@ -28,6 +29,25 @@ use rayon::prelude::*;
 use tiff::encoder::{colortype, Rational, TiffEncoder, TiffValue};
 use tiff::tags::{ResolutionUnit, Tag, Type as TiffType};
 
+mod catalog;
+mod cms;
+mod compliance;
+mod config;
+mod icc;
+mod jp2;
+mod quantize;
+mod resize;
+mod tiles;
+
+use cms::{round_trip_error, same_profile, transform_in_place_parallel, SharedTransform, TransformCache};
+use catalog::IccCatalog;
+use config::Config;
+use icc::{ChromaticAdaptation, IccVersion};
+use jp2::Jp2Params;
+use quantize::{quantize_rgb16_to_rgb8_stream_dither, DitherMethod};
+use resize::{ResizeFilter, ResizeSpace, ResizeSpec};
+use tiles::TileFormat;
+
 #[derive(Debug, Copy, Clone, ValueEnum)]
 enum RenderIntent {
     Perceptual,
@@ -46,10 +66,42 @@ impl From<RenderIntent> for Intent {
     }
 }
 
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum Precision {
+    /// Low-resolution precalculated device link (17 grid points)
+    Fast,
+    /// lcms2 defaults (33 grid points, optimizations on)
+    Default,
+    /// High-resolution precalculated device link (49 grid points)
+    High,
+    /// No optimization: every pixel goes through the full profile pipeline
+    Exact,
+}
+impl Precision {
+    /// Transform flags for this setting. `grid_points` overrides the grid size
+    /// of the precalculated device link (ignored by `exact`).
+    fn flags(self, grid_points: Option<u8>) -> Flags<DisallowCache> {
+        let mut flags = Flags::NO_CACHE;
+        flags = match self {
+            Precision::Fast => flags | Flags::LOWRES_PRECALC,
+            Precision::Default => flags,
+            Precision::High => flags | Flags::HIGHRES_PRECALC,
+            Precision::Exact => return flags | Flags::NO_OPTIMIZE,
+        };
+        if let Some(n) = grid_points {
+            // cmsFLAGS_GRIDPOINTS(n)
+            flags.0 |= u32::from(n) << 16;
+        }
+        flags
+    }
+}
+
 #[derive(Debug, Copy, Clone, ValueEnum)]
 enum BitDepth {
     B8,
     B16,
+    /// 32-bit float per channel (TIFF only, SampleFormat=IEEEFP, unclipped)
+    B32f,
 }
 
 #[derive(Debug, Copy, Clone, ValueEnum)]
@@ -59,6 +111,138 @@ enum DetectInputIcc {
     File,
 }
 
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum MissingIcc {
+    /// Assume sRGB silently
+    AssumeSrgb,
+    /// Assume sRGB and print a warning
+    Warn,
+    /// Fail the file
+    Error,
+    /// Skip the file (no output is written)
+    Skip,
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum Jp2Icc {
+    /// No check
+    Off,
+    /// Print a warning
+    Warn,
+    /// Fail the file
+    Error,
+    /// Replace the profile by a matrix/TRC approximation (pixels are converted to it)
+    Approximate,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Pages {
+    /// `multipage` for TIFF outputs, `split` otherwise
+    Auto,
+    /// Only the first full-resolution page
+    First,
+    /// All pages into one multi-page TIFF
+    Multipage,
+    /// One numbered file per page (`<stem>_p0001.<ext>`)
+    Split,
+}
+
+/// EXIF/TIFF Orientation handling.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Orientation {
+    /// Rotate/flip the pixels upright; no Orientation tag is written (default for UC presets)
+    Apply,
+    /// Keep the pixels and write the Orientation tag to TIFF/JPEG/PNG outputs (default for ndk-mc)
+    Preserve,
+    /// Keep the pixels and drop the tag
+    Ignore,
+}
+
+/// --missing-resolution: what to write when the input states no resolution.
+#[derive(Debug, Copy, Clone)]
+enum MissingResolution {
+    /// Fail the file
+    Error,
+    /// Write no resolution
+    Omit,
+    /// Write this many dpi
+    Assume(f64),
+}
+
+fn parse_missing_resolution(s: &str) -> Result<MissingResolution, String> {
+    match s {
+        "error" => Ok(MissingResolution::Error),
+        "omit" => Ok(MissingResolution::Omit),
+        _ => {
+            let dpi = s
+                .strip_prefix("assume=")
+                .ok_or_else(|| format!("'{s}' is not error, omit or assume=<dpi>"))?;
+            let dpi: f64 = dpi.parse().map_err(|e| format!("assume=<dpi>: {e}"))?;
+            if dpi > 0.0 && dpi <= u16::MAX as f64 {
+                Ok(MissingResolution::Assume(dpi))
+            } else {
+                Err(format!("{dpi} dpi is out of range"))
+            }
+        }
+    }
+}
+
+/// --derivative: one of several outputs written from a single decode.
+/// Unset fields inherit the global options.
+#[derive(Debug, Clone, Default)]
+struct Derivative {
+    preset: Option<String>,
+    /// Options of `preset` resolved against the command line (set by `parse_args`)
+    base: Option<Box<Args>>,
+    out_icc: Option<PathBuf>,
+    out_depth: Option<BitDepth>,
+    /// Output extension; default: that of OUTPUT (batch: --out-ext)
+    ext: Option<String>,
+    /// Appended to the output file stem
+    suffix: String,
+}
+
+fn parse_derivative(s: &str) -> Result<Derivative, String> {
+    let mut d = Derivative::default();
+    for field in s.split(',').filter(|f| !f.is_empty()) {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or_else(|| format!("'{field}' is not <key>=<value>"))?;
+        match key {
+            "preset" => d.preset = Some(value.to_string()),
+            "out-icc" => d.out_icc = Some(PathBuf::from(value)),
+            "depth" => d.out_depth = Some(BitDepth::from_str(value, true)?),
+            "ext" => d.ext = Some(normalize_out_ext(value).map_err(|e| e.to_string())?),
+            "suffix" => d.suffix = value.to_string(),
+            _ => return Err(format!("unknown key '{key}' (preset, out-icc, depth, ext, suffix)")),
+        }
+    }
+    Ok(d)
+}
+
+impl Derivative {
+    /// Options of this derivative: the global ones with its fields applied.
+    fn args(&self, args: &Args) -> Args {
+        let base = self.base.as_deref().unwrap_or(args);
+        Args {
+            out_icc: self.out_icc.clone().or_else(|| base.out_icc.clone()),
+            out_depth: self.out_depth.or(base.out_depth),
+            derivative: Vec::new(),
+            ..base.clone()
+        }
+    }
+
+    /// `<stem><suffix>.<ext>` next to `output`.
+    fn path(&self, output: &Path) -> PathBuf {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = match &self.ext {
+            Some(ext) => ext.clone(),
+            None => output.extension().map_or("tif".into(), |e| e.to_string_lossy().into_owned()),
+        };
+        output.with_file_name(format!("{stem}{}.{ext}", self.suffix))
+    }
+}
+
 #[derive(Debug, Copy, Clone, ValueEnum)]
 enum ToneMap {
     None,
@@ -77,19 +261,54 @@ enum Preset {
     /// Convenience preset for NDK User Copy II (maps/manuscripts/old prints)
     #[value(name = "ndk-uc-ii")]
     NdkUcII,
+    /// FADGI 4-star master: 16-bit eciRGB v2, at least 400 ppi
+    #[value(name = "fadgi-4-star")]
+    Fadgi4Star,
+    /// FADGI 3-star master: 16-bit eciRGB v2, at least 400 ppi
+    #[value(name = "fadgi-3-star")]
+    Fadgi3Star,
+    /// FADGI 2-star master: 8-bit Adobe RGB (1998), at least 300 ppi
+    #[value(name = "fadgi-2-star")]
+    Fadgi2Star,
+    /// FADGI 1-star master: 8-bit sRGB, at least 150 ppi
+    #[value(name = "fadgi-1-star")]
+    Fadgi1Star,
+    /// Metamorfoze master: 16-bit eciRGB v2, at least 300 ppi
+    #[value(name = "metamorfoze")]
+    Metamorfoze,
+    /// ISO 19264-1 level A master: 16-bit eciRGB v2
+    #[value(name = "iso19264-a")]
+    Iso19264A,
+    /// ISO 19264-1 level B master: 16-bit eciRGB v2
+    #[value(name = "iso19264-b")]
+    Iso19264B,
+    /// ISO 19264-1 level C master: 8-bit sRGB
+    #[value(name = "iso19264-c")]
+    Iso19264C,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "nclr",
     version,
-    about = "NDK-oriented ICC color conversion and 16↔8 bit-depth conversion using LittleCMS2 (lcms2 crate)."
+    about = "NDK-oriented ICC color conversion and 16↔8 bit-depth conversion using LittleCMS2 (lcms2 crate).",
+    // Options expanded from a config preset come first; the user's own override them.
+    args_override_self = true
 )]
 struct Args {
-    /// High-level convenience preset that fills recommended defaults.
-    /// Explicit options always take precedence.
-    #[arg(long, value_enum)]
-    preset: Option<Preset>,
+    /// High-level convenience preset that fills recommended defaults: ndk-mc, ndk-uc-i,
+    /// ndk-uc-ii, or a preset defined in the config file. Explicit options always take precedence.
+    #[arg(long, value_name = "NAME")]
+    preset: Option<String>,
+
+    /// Config file with user-defined presets. Default: <config dir>/nclr/config.toml
+    /// and ./nclr.toml (both are read when present).
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// ICC policy of the selected preset (resolved from --preset)
+    #[arg(skip)]
+    policy: Option<Preset>,
 
     /// Input image (TIFF/PNG/JPEG...). For 16-bit workflows use TIFF/PNG.
     #[arg(short = 'i', long)]
@@ -97,16 +316,21 @@ struct Args {
 
     /// Output path. If INPUT is a file, this must be a file path (extension selects format).
     /// If INPUT is a directory, this must be an output directory path.
-    #[arg(short = 'o', long)]
-    output: PathBuf,
+    #[arg(short = 'o', long, required_unless_present = "check")]
+    output: Option<PathBuf>,
+
+    /// Check existing files (INPUT) against the file-level requirements of an
+    /// archival --preset (fadgi-*, metamorfoze, iso19264-*) instead of converting.
+    #[arg(long, default_value_t = false)]
+    check: bool,
 
     /// If INPUT is a directory, scan it (and optionally its subdirectories) for images.
-    /// Supported extensions: tif, tiff, png, jpg, jpeg.
+    /// Supported extensions: tif, tiff, png, jpg, jpeg, jp2, jpx.
     #[arg(short = 'r', long, default_value_t = false)]
     recursive: bool,
 
-    /// When INPUT is a directory, choose output extension for generated files.
-    /// Default: "tif".
+    /// When INPUT is a directory, choose output extension for generated files
+    /// (tif, tiff, png, jpg, jpeg, jp2). Default: "tif".
     #[arg(long, default_value = "tif")]
     out_ext: String,
 
@@ -119,19 +343,67 @@ struct Args {
     #[arg(long, default_value_t = false)]
     overwrite: bool,
 
+    /// Write several outputs from one decode, e.g.
+    /// `--derivative preset=ndk-mc,suffix=_mc --derivative preset=ndk-uc-ii,depth=b8,ext=jpg,suffix=_uc`.
+    /// Keys: preset, out-icc, depth, ext, suffix; other options are shared. Each derivative is
+    /// written to `<stem><suffix>.<ext>` next to OUTPUT (batch: in the output directory).
+    #[arg(long, value_parser = parse_derivative, conflicts_with = "tiles")]
+    derivative: Vec<Derivative>,
+
+    /// Multi-page TIFF inputs: which pages to convert and how to write them.
+    /// Reduced-resolution subfiles (thumbnails) are never counted as pages.
+    #[arg(long, value_enum, default_value_t = Pages::Auto)]
+    pages: Pages,
+
     /// Parallel jobs for directory conversion (0 = auto).
     #[arg(long, default_value_t = 0)]
     jobs: usize,
 
+    /// Threads used within a single image for the ICC transform and 16->8 quantization.
+    /// 0 = share the current pool (all cores in single-file mode, the --jobs pool in batch mode).
+    /// N > 0 gives every worker its own pool of N threads (built once, reused for each image),
+    /// so batch mode uses up to jobs × N threads.
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
     /// How to pick input ICC.
     #[arg(long, value_enum, default_value_t = DetectInputIcc::Auto)]
     detect_input_icc: DetectInputIcc,
 
+    /// What to do in auto mode when the input has no embedded ICC profile and
+    /// EXIF gives no colour space hint (ColorSpace=sRGB, InteroperabilityIndex R98/R03).
+    #[arg(long, value_enum, default_value_t = MissingIcc::Warn)]
+    missing_icc: MissingIcc,
+
+    /// What to do when the input states no resolution (TIFF tags, JP2 `res `,
+    /// PNG pHYs, JFIF density or EXIF): error, omit (write none) or assume=<dpi>.
+    /// Default: error for the archival presets, omit otherwise.
+    #[arg(long, value_parser = parse_missing_resolution)]
+    missing_resolution: Option<MissingResolution>,
+
+    /// EXIF/TIFF Orientation of the input: apply (rotate/flip the pixels),
+    /// preserve (write the tag) or ignore. Default: preserve for ndk-mc, apply otherwise.
+    #[arg(long, value_enum)]
+    orientation: Option<Orientation>,
+
     /// ICC profile file used when --detect-input-icc=file.
+    /// `builtin:<name>` selects a built-in profile (srgb, ecirgb-v2, adobe-rgb-1998,
+    /// prophoto, display-p3, gray-gamma-2.2, gray-srgb).
     #[arg(long)]
     input_icc_file: Option<PathBuf>,
 
-    /// Output ICC profile file.
+    /// Directory of known ICC profiles (.icc/.icm), indexed by MD5 and description.
+    /// Catalogue entries can be named as replacements in --icc-map.
+    #[arg(long)]
+    icc_dir: Option<PathBuf>,
+
+    /// Substitution map for known-bad embedded profiles, one rule per line:
+    /// `<md5|profile-id> = <replacement>` or `desc:<description> = <replacement>`.
+    /// Replacement: a profile path, `builtin:<name>`, or an --icc-dir entry (MD5 or description).
+    #[arg(long)]
+    icc_map: Option<PathBuf>,
+
+    /// Output ICC profile file, or `builtin:<name>` (see --input-icc-file).
     ///
     /// Policy:
     /// - UC-I: ignored unless --force-out-icc
@@ -144,8 +416,31 @@ struct Args {
     #[arg(long, value_enum)]
     intent: Option<RenderIntent>,
 
-    /// Black Point Compensation (BPC). Default: true.
-    #[arg(long, default_value_t = true)]
+    /// Observer adaptation state for the absolute colorimetric intent
+    /// (1 = fully adapted to the media white, 0 = no adaptation, in between = partial).
+    #[arg(long, default_value_t = 1.0, value_parser = parse_adaptation_state)]
+    adaptation_state: f64,
+
+    /// Chromatic adaptation transform used to adapt synthesized profiles (built-in sRGB) to D50.
+    #[arg(long, value_enum, default_value_t = ChromaticAdaptation::Bradford)]
+    chromatic_adaptation: ChromaticAdaptation,
+
+    /// Transform precision: fast | default | high | exact.
+    /// Controls the lcms2 precalculated device link; `exact` disables optimization.
+    #[arg(long, value_enum, default_value_t = Precision::Default)]
+    precision: Precision,
+
+    /// Grid points of the precalculated device link (overrides the --precision grid size).
+    #[arg(long, value_parser = clap::value_parser!(u8).range(2..))]
+    grid_points: Option<u8>,
+
+    /// Measure the round-trip error (input -> output -> input, CIEDE2000) of the
+    /// transform for each file and print it.
+    #[arg(long, default_value_t = false)]
+    precision_check: bool,
+
+    /// Black Point Compensation (BPC). Default: true; `--bpc` alone means true.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
     bpc: bool,
 
     /// Output bit depth (overrides policy defaults).
@@ -156,16 +451,77 @@ struct Args {
     #[arg(long, value_enum)]
     tone_map: Option<ToneMap>,
 
-    /// Apply Floyd–Steinberg dithering after 16->8 quantization.
+    /// Apply dithering after 16->8 quantization.
     #[arg(long)]
     dither: Option<bool>,
 
+    /// Dithering algorithm used when dithering is enabled.
+    /// Setting a method implies --dither true unless --dither false is given.
+    /// Default: floyd-steinberg.
+    #[arg(long, value_enum)]
+    dither_method: Option<DitherMethod>,
+
+    /// Resize: long-edge=<px> (downscale to at most this long edge), <percent>%
+    /// or dpi=<dpi> (needs the input resolution). Resampled in linear light;
+    /// the written resolution is scaled accordingly.
+    #[arg(long, value_parser = resize::parse_resize)]
+    resize: Option<ResizeSpec>,
+
+    /// Resampling kernel for --resize.
+    #[arg(long, value_enum, default_value_t = ResizeFilter::Lanczos3)]
+    resize_filter: ResizeFilter,
+
+    /// Working space whose linear-light version --resize resamples in.
+    #[arg(long, value_enum, default_value_t = ResizeSpace::Output)]
+    resize_space: ResizeSpace,
+
+    /// Write a static tile pyramid instead of an image. OUTPUT (in batch mode
+    /// `<stem><suffix>` in the output directory, without extension) names the
+    /// tile set: a directory for iiif and zoomify, `<OUTPUT>.dzi` and
+    /// `<OUTPUT>_files/` for dzi. Tiles are 8-bit sRGB JPEG; only the first page is tiled.
+    #[arg(long, value_enum, conflicts_with = "no_icc")]
+    tiles: Option<TileFormat>,
+
+    /// Tile edge in pixels for --tiles. Default: 512 (iiif), 254 (dzi), 256 (zoomify).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(16..=8192))]
+    tile_size: Option<u32>,
+
+    /// Pixels each DZI tile shares with its neighbours.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(0..=16))]
+    tile_overlap: u32,
+
+    /// Scale factors of the IIIF tile pyramid, e.g. 1,2,4,8.
+    /// Default: powers of two until the whole image fits in one tile.
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(1..))]
+    tile_scale_factors: Option<Vec<u32>>,
+
+    /// JPEG quality of the tiles (1-100).
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    tile_quality: u8,
+
+    /// Base URI of the IIIF image service; the `id` in info.json is
+    /// `<base>/<directory name>`. Required with --tiles iiif.
+    #[arg(long, value_name = "URL", required_if_eq("tiles", "iiif"))]
+    iiif_base: Option<String>,
+
     /// Write the output ICC profile as a sidecar next to each output image.
     ///
     /// The sidecar path is derived from the output image path by changing the extension to `.icc`.
     #[arg(long, default_value_t = false)]
     write_icc: bool,
 
+    /// ICC version of the output profile written to files (embedded and sidecar).
+    /// Matrix/TRC profiles are re-serialized via lcms2; LUT-based profiles are rejected
+    /// unless they already have the requested version.
+    #[arg(long, value_enum, default_value_t = IccVersion::Keep)]
+    icc_version: IccVersion,
+
+    /// Check that the output profile qualifies as JP2 restricted ICC (colr method 2:
+    /// matrix/TRC input or display profile, XYZ PCS).
+    /// Default: error for .jp2 outputs (the profile goes into `colr`), off otherwise.
+    #[arg(long, value_enum)]
+    jp2_icc: Option<Jp2Icc>,
+
     /// Override NDK policy and allow output ICC for UC-I.
     #[arg(long, default_value_t = false)]
     force_out_icc: bool,
@@ -177,6 +533,26 @@ struct Args {
     /// If set, do not apply ICC transform; only convert bit depth / drop alpha.
     #[arg(long, default_value_t = false)]
     no_icc: bool,
+
+    /// After writing, re-decode the output and verify its pixel hash (MD5).
+    /// When input and output profiles are identical (transform skipped), the output
+    /// must match the decoded input pixels bit for bit. Default: true for ndk-mc.
+    #[arg(long)]
+    verify: Option<bool>,
+
+    /// Always decode to 16-bit and transform in 16-bit precision.
+    /// By default 8-bit inputs with 8-bit output (no tone map, no dither) use a native 8-bit transform.
+    #[arg(long = "force-16bit", default_value_t = false)]
+    force_16bit: bool,
+}
+
+fn parse_adaptation_state(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&v) {
+        Ok(v)
+    } else {
+        Err(format!("{v} is not in 0..1"))
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -185,19 +561,22 @@ struct Effective {
     out_depth: BitDepth,
     intent: RenderIntent,
     tone_map: ToneMap,
-    dither: bool,
+    dither: Option<DitherMethod>,
     bpc: bool,
+    verify: bool,
+    orientation: Orientation,
+    missing_resolution: MissingResolution,
 }
 
 /// Apply preset defaults, but do NOT override explicit user options.
 fn compute_effective(args: &Args) -> Effective {
     // Default preset is NDK UC-II if not specified
-    let preset = args.preset.unwrap_or(Preset::NdkUcII);
+    let preset = args.policy.unwrap_or(Preset::NdkUcII);
 
     // Base defaults
     let mut intent = args.intent.unwrap_or(RenderIntent::Perceptual);
     let mut tone_map = args.tone_map.unwrap_or(ToneMap::None);
-    let mut dither = args.dither.unwrap_or(false);
+    let mut dither = args.dither.unwrap_or(args.dither_method.is_some());
     let bpc = args.bpc;
 
     // Preset-specific defaults (only fill what the user didn't specify)
@@ -209,7 +588,7 @@ fn compute_effective(args: &Args) -> Effective {
             if args.tone_map.is_none() {
                 tone_map = ToneMap::None;
             }
-            if args.dither.is_none() {
+            if args.dither.is_none() && args.dither_method.is_none() {
                 dither = false;
             }
         }
@@ -221,18 +600,47 @@ fn compute_effective(args: &Args) -> Effective {
             if args.tone_map.is_none() {
                 tone_map = ToneMap::None;
             }
-            if args.dither.is_none() {
+            if args.dither.is_none() && args.dither_method.is_none() {
                 dither = false;
             }
         }
+        _ => {
+            // Archival masters: colorimetric, no tone mapping
+            if args.intent.is_none() {
+                intent = RenderIntent::Relative;
+            }
+            if args.tone_map.is_none() {
+                tone_map = ToneMap::None;
+            }
+        }
     }
 
+    let archival = compliance::requirements(preset);
+
     // Output depth default depends on the preset
-    let out_depth = args.out_depth.unwrap_or_else(|| match preset {
-        Preset::NdkMc => BitDepth::B16,
+    let out_depth = args.out_depth.unwrap_or(match (preset, archival) {
+        (_, Some(req)) => req.depth,
+        (Preset::NdkMc, _) => BitDepth::B16,
         _ => BitDepth::B8,
     });
 
+    let dither = dither.then(|| args.dither_method.unwrap_or(DitherMethod::FloydSteinberg));
+
+    // MC rewrites must not touch pixels, and masters must hold what was written; verify by default.
+    let verify = args.verify.unwrap_or(matches!(preset, Preset::NdkMc) || archival.is_some());
+
+    // Masters must state their resolution.
+    let missing_resolution = args.missing_resolution.unwrap_or(match archival {
+        Some(_) => MissingResolution::Error,
+        None => MissingResolution::Omit,
+    });
+
+    // MC keeps the scanned pixels as they are; derivatives are viewed upright.
+    let orientation = args.orientation.unwrap_or(match preset {
+        Preset::NdkMc => Orientation::Preserve,
+        _ => Orientation::Apply,
+    });
+
     Effective {
         preset,
         out_depth,
@@ -240,17 +648,45 @@ fn compute_effective(args: &Args) -> Effective {
         tone_map,
         dither,
         bpc,
+        verify,
+        orientation,
+        missing_resolution,
     }
 }
 
 // ---------------- TIFF metadata (ICC + resolution) ----------------
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 struct TiffMeta {
     icc: Option<Vec<u8>>,
     x_res: Option<Rational>,
     y_res: Option<Rational>,
     unit: Option<ResolutionUnit>,
+    /// IFD the metadata was read from (0 for non-TIFF inputs)
+    ifd: usize,
+    /// Orientation (2..=8) to write to the output; 1 (upright) is not kept
+    orientation: Option<u16>,
+    /// Orientation applied to the pixels before conversion (--orientation apply)
+    reorient: Option<u16>,
+    /// EXIF ColorSpace (1 = sRGB, 65535 = uncalibrated)
+    exif_colour_space: Option<u16>,
+    /// EXIF InteroperabilityIndex ("R98", "R03")
+    exif_interop: Option<String>,
+    /// JP2/JPX enumerated colour space (`colr` method 1)
+    jp2_enumerated: Option<u32>,
+}
+
+/// Dots per inch for `ppm` pixels per metre.
+///
+/// `pHYs` and `resc` store whole pixels per metre, so a value within half a
+/// pixel per metre of a whole dpi is taken as that dpi (11811 -> 300/1).
+fn dpi_rational(ppm: f64) -> Rational {
+    let dpi = ppm * 0.0254;
+    if (dpi - dpi.round()).abs() <= 0.5 * 0.0254 {
+        Rational { n: dpi.round() as u32, d: 1 }
+    } else {
+        Rational { n: (dpi * 1000.0).round() as u32, d: 1000 }
+    }
 }
 
 fn file_ext_lower(p: &Path) -> String {
@@ -264,6 +700,158 @@ fn is_tiff_path(p: &Path) -> bool {
     matches!(file_ext_lower(p).as_str(), "tif" | "tiff")
 }
 
+fn is_jp2_path(p: &Path) -> bool {
+    matches!(file_ext_lower(p).as_str(), "jp2" | "jpx")
+}
+
+/// Colour specification and resolution of a JP2/JPX file, as TIFF metadata.
+fn read_jp2_meta(path: &Path) -> Result<TiffMeta> {
+    let header = jp2::read_header(path)?;
+    let (icc, jp2_enumerated) = match header.colour {
+        Some(jp2::Colour::Icc(bytes)) => (Some(bytes), None),
+        Some(jp2::Colour::Enumerated(cs)) => (None, Some(cs)),
+        None => (None, None),
+    };
+    let (x_res, y_res) = match header.resolution_ppm {
+        Some((x, y)) => (Some(dpi_rational(x)), Some(dpi_rational(y))),
+        None => (None, None),
+    };
+    Ok(TiffMeta {
+        icc,
+        x_res,
+        y_res,
+        unit: Some(ResolutionUnit::Inch),
+        jp2_enumerated,
+        ..TiffMeta::default()
+    })
+}
+
+/// Resolution and orientation of a PNG/JPEG file: `pHYs` or JFIF density,
+/// else EXIF XResolution/YResolution/ResolutionUnit; EXIF Orientation and
+/// colour space. The ICC is read separately.
+fn read_raster_meta(path: &Path) -> Result<TiffMeta> {
+    let native = match file_ext_lower(path).as_str() {
+        "png" => read_png_resolution(path)?,
+        "jpg" | "jpeg" => read_jfif_resolution(path)?,
+        _ => None,
+    };
+    let exif = read_exif(path);
+    let mut meta = native
+        .or_else(|| exif.as_ref().and_then(exif_resolution))
+        .unwrap_or_default();
+    let Some(exif) = exif else {
+        return Ok(meta);
+    };
+    let uint = |tag| exif.get_field(tag, exif::In::PRIMARY).and_then(|f| f.value.get_uint(0));
+    meta.orientation = uint(exif::Tag::Orientation).and_then(valid_orientation);
+    meta.exif_colour_space = uint(exif::Tag::ColorSpace).and_then(|v| u16::try_from(v).ok());
+    meta.exif_interop = exif
+        .get_field(exif::Tag::InteroperabilityIndex, exif::In::PRIMARY)
+        .and_then(|f| match &f.value {
+            exif::Value::Ascii(v) => v.first().map(|s| ascii_value(s)),
+            _ => None,
+        });
+    Ok(meta)
+}
+
+/// An EXIF/TIFF ASCII value without its NUL terminator and padding.
+fn ascii_value(b: &[u8]) -> String {
+    String::from_utf8_lossy(b.split(|&c| c == 0).next().unwrap_or_default())
+        .trim()
+        .to_string()
+}
+
+/// EXIF of a JPEG/PNG file, if any (TIFF EXIF is read by `read_ifd`).
+fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let file = File::open(path).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()
+}
+
+/// Orientation values that need a rotation or flip (2..=8).
+fn valid_orientation(v: u32) -> Option<u16> {
+    (2..=8).contains(&v).then_some(v as u16)
+}
+
+/// PNG `pHYs` in pixels per metre (unit "unknown" only gives an aspect ratio).
+fn read_png_resolution(path: &Path) -> Result<Option<TiffMeta>> {
+    let f = File::open(path).with_context(|| format!("Open {}", path.display()))?;
+    let reader = png::Decoder::new(BufReader::new(f))
+        .read_info()
+        .with_context(|| format!("Read PNG header of {}", path.display()))?;
+    Ok(reader
+        .info()
+        .pixel_dims
+        .filter(|d| d.unit == png::Unit::Meter && d.xppu > 0 && d.yppu > 0)
+        .map(|d| TiffMeta {
+            x_res: Some(dpi_rational(d.xppu as f64)),
+            y_res: Some(dpi_rational(d.yppu as f64)),
+            unit: Some(ResolutionUnit::Inch),
+            ..TiffMeta::default()
+        }))
+}
+
+/// JFIF APP0 density (units 1 = dpi, 2 = dots per cm; 0 is an aspect ratio only).
+fn read_jfif_resolution(path: &Path) -> Result<Option<TiffMeta>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    for (marker, seg) in jpeg_segments(&data) {
+        if marker != 0xE0 || seg.len() < 12 || &seg[..5] != b"JFIF\0" {
+            continue;
+        }
+        let unit = match seg[7] {
+            1 => ResolutionUnit::Inch,
+            2 => ResolutionUnit::Centimeter,
+            _ => return Ok(None),
+        };
+        let x = u16::from_be_bytes([seg[8], seg[9]]) as u32;
+        let y = u16::from_be_bytes([seg[10], seg[11]]) as u32;
+        if x == 0 || y == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(TiffMeta {
+            x_res: Some(Rational { n: x, d: 1 }),
+            y_res: Some(Rational { n: y, d: 1 }),
+            unit: Some(unit),
+            ..TiffMeta::default()
+        }));
+    }
+    Ok(None)
+}
+
+/// EXIF XResolution/YResolution/ResolutionUnit (unit defaults to inch, as in TIFF;
+/// 1 = no absolute unit).
+fn exif_resolution(exif: &exif::Exif) -> Option<TiffMeta> {
+    let rational = |tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Rational(v) => v.first().filter(|r| r.num > 0 && r.denom > 0).map(|r| Rational {
+            n: r.num,
+            d: r.denom,
+        }),
+        _ => None,
+    };
+    let x_res = rational(exif::Tag::XResolution);
+    let y_res = rational(exif::Tag::YResolution);
+    if x_res.is_none() && y_res.is_none() {
+        return None;
+    }
+    let unit = match exif
+        .get_field(exif::Tag::ResolutionUnit, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+    {
+        Some(1) => ResolutionUnit::None,
+        Some(3) => ResolutionUnit::Centimeter,
+        _ => ResolutionUnit::Inch,
+    };
+    Some(TiffMeta {
+        x_res,
+        y_res,
+        unit: Some(unit),
+        ..TiffMeta::default()
+    })
+}
+
 fn read_exact_at(f: &mut File, off: u64, buf: &mut [u8]) -> Result<()> {
     f.seek(SeekFrom::Start(off))
         .with_context(|| format!("Seek @ {off}"))?;
@@ -297,9 +885,14 @@ fn read_u64_endian(b: [u8; 8], le: bool) -> u64 {
 /// Minimal TIFF/BigTIFF reader for:
 /// - ICCProfile (34675)
 /// - XResolution (282), YResolution (283), ResolutionUnit (296)
+/// - NewSubfileType (254) / SubfileType (255)
+/// - Orientation (274)
+/// - EXIF ColorSpace (40961) and InteroperabilityIndex (1) in the EXIF (34665)
+///   and Interoperability (40965) sub-IFDs
 ///
-/// Reads only IFD0 + referenced value blocks.
-fn read_tiff_meta(path: &Path) -> Result<TiffMeta> {
+/// Walks the main IFD chain (IFD0, IFD1, ...) and returns the pages, i.e.
+/// every IFD that is not a reduced-resolution subfile (thumbnail, preview).
+fn read_tiff_pages(path: &Path) -> Result<Vec<TiffMeta>> {
     let mut f = File::open(path).with_context(|| format!("Open TIFF: {}", path.display()))?;
 
     // Header
@@ -313,224 +906,278 @@ fn read_tiff_meta(path: &Path) -> Result<TiffMeta> {
     };
 
     let magic = read_u16_endian([head[2], head[3]], le);
+    let (big, mut ifd_off) = match magic {
+        // Classic TIFF
+        42 => (false, read_u32_endian([head[4], head[5], head[6], head[7]], le) as u64),
+        // BigTIFF
+        43 => {
+            let off_size = read_u16_endian([head[4], head[5]], le);
+            if off_size != 8 {
+                bail!("Unsupported BigTIFF offset size: {}", off_size);
+            }
+            let off = read_u64_endian(
+                [
+                    head[8], head[9], head[10], head[11], head[12], head[13], head[14], head[15],
+                ],
+                le,
+            );
+            (true, off)
+        }
+        _ => bail!("Unknown TIFF magic: {}", magic),
+    };
+
+    let mut pages = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut ifd = 0usize;
+    while ifd_off != 0 {
+        if !seen.insert(ifd_off) {
+            bail!("TIFF IFD chain loops at offset {ifd_off}");
+        }
+        let (meta, reduced, next) = read_ifd(&mut f, ifd_off, le, big, ifd)?;
+        if !reduced {
+            pages.push(meta);
+        }
+        ifd_off = next;
+        ifd += 1;
+    }
+    Ok(pages)
+}
+
+/// One IFD entry of a known value type; the value is read on demand.
+struct IfdEntry {
+    tag: u16,
+    /// Value length in bytes (count × type size)
+    len: u64,
+    /// Value field of the entry (4 bytes classic TIFF, 8 BigTIFF)
+    field: [u8; 8],
+    /// Offset of the value; `None` when it is stored in `field`
+    offset: Option<u64>,
+}
+
+impl IfdEntry {
+    fn bytes(&self, f: &mut File) -> Result<Vec<u8>> {
+        let Some(offset) = self.offset else {
+            return Ok(self.field[..self.len as usize].to_vec());
+        };
+        let mut v = vec![0u8; self.len as usize];
+        read_exact_at(f, offset, &mut v)?;
+        Ok(v)
+    }
+}
 
+/// Entries of the IFD at `ifd_off` (types we do not read are left out) and
+/// the next IFD offset.
+fn read_ifd_entries(f: &mut File, ifd_off: u64, le: bool, big: bool) -> Result<(Vec<IfdEntry>, u64)> {
     // TIFF type sizes (subset we need)
     fn type_size(t: u16) -> Option<u64> {
         match t {
             1 => Some(1),  // BYTE
+            2 => Some(1),  // ASCII
             3 => Some(2),  // SHORT
             4 => Some(4),  // LONG
             5 => Some(8),  // RATIONAL (2x u32)
             7 => Some(1),  // UNDEFINED
+            13 => Some(4), // IFD
             16 => Some(8), // LONG8 (BigTIFF)
+            18 => Some(8), // IFD8 (BigTIFF)
             _ => None,
         }
     }
 
+    // Classic TIFF: 2-byte count, 12-byte entries, 4-byte values/offsets;
+    // BigTIFF: 8-byte count, 20-byte entries, 8-byte values/offsets.
+    let (count_len, entry_len, value_len) = if big { (8, 20, 8) } else { (2, 12, 4) };
+    let read_uint = |b: &[u8]| -> u64 {
+        match b.len() {
+            2 => read_u16_endian([b[0], b[1]], le) as u64,
+            4 => read_u32_endian([b[0], b[1], b[2], b[3]], le) as u64,
+            _ => read_u64_endian([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]], le),
+        }
+    };
+
+    let mut nbuf = [0u8; 8];
+    read_exact_at(f, ifd_off, &mut nbuf[..count_len])?;
+    let n = read_uint(&nbuf[..count_len]);
+
+    let mut entries = Vec::new();
+    let mut ent_off = ifd_off + count_len as u64;
+    let mut ent = [0u8; 20];
+    for _ in 0..n {
+        let ent = &mut ent[..entry_len];
+        read_exact_at(f, ent_off, ent)?;
+        ent_off += entry_len as u64;
+
+        let tag = read_u16_endian([ent[0], ent[1]], le);
+        let ty = read_u16_endian([ent[2], ent[3]], le);
+        let count = read_uint(&ent[4..4 + value_len]);
+        let value_field = &ent[4 + value_len..];
+
+        let Some(tsz) = type_size(ty) else {
+            continue;
+        };
+        let len = count.saturating_mul(tsz);
+        let mut field = [0u8; 8];
+        field[..value_len].copy_from_slice(value_field);
+        // Values that fit in the field are stored in it
+        let offset = (len > value_len as u64).then(|| read_uint(value_field));
+        entries.push(IfdEntry { tag, len, field, offset });
+    }
+
+    let mut next = [0u8; 8];
+    read_exact_at(f, ent_off, &mut next[..value_len])?;
+    Ok((entries, read_uint(&next[..value_len])))
+}
+
+/// Read one IFD: (metadata, reduced-resolution subfile, next IFD offset).
+fn read_ifd(f: &mut File, ifd_off: u64, le: bool, big: bool, ifd: usize) -> Result<(TiffMeta, bool, u64)> {
     let mut meta = TiffMeta {
-        icc: None,
-        x_res: None,
-        y_res: None,
-        unit: None,
+        ifd,
+        ..TiffMeta::default()
     };
+    let mut reduced = false;
 
     let icc_tag: u16 = 34675;
     let xres_tag: u16 = 282;
     let yres_tag: u16 = 283;
     let unit_tag: u16 = 296;
+    let new_subfile_tag: u16 = 254;
+    let subfile_tag: u16 = 255;
+    let orientation_tag: u16 = 274;
+    let exif_ifd_tag: u16 = 34665;
+
+    let rational = |b: &[u8]| -> Option<Rational> {
+        if b.len() < 8 {
+            return None;
+        }
+        let n = read_u32_endian([b[0], b[1], b[2], b[3]], le);
+        let d = read_u32_endian([b[4], b[5], b[6], b[7]], le);
+        (d != 0).then_some(Rational { n, d })
+    };
 
-    if magic == 42 {
-        // Classic TIFF
-        let ifd0_off = read_u32_endian([head[4], head[5], head[6], head[7]], le) as u64;
-
-        let mut nbuf = [0u8; 2];
-        read_exact_at(&mut f, ifd0_off, &mut nbuf)?;
-        let n = read_u16_endian(nbuf, le) as u64;
-
-        let mut ent_off = ifd0_off + 2;
-        for _ in 0..n {
-            let mut ent = [0u8; 12];
-            read_exact_at(&mut f, ent_off, &mut ent)?;
-            ent_off += 12;
-
-            let tag = read_u16_endian([ent[0], ent[1]], le);
-            let ty = read_u16_endian([ent[2], ent[3]], le);
-            let count = read_u32_endian([ent[4], ent[5], ent[6], ent[7]], le) as u64;
-            let value_or_off = read_u32_endian([ent[8], ent[9], ent[10], ent[11]], le) as u64;
-
-            let tsz = match type_size(ty) {
-                Some(s) => s,
-                None => continue,
-            };
-            let bytes_len = count.saturating_mul(tsz);
-
-            let get_bytes = |f: &mut File| -> Result<Vec<u8>> {
-                if bytes_len == 0 {
-                    return Ok(Vec::new());
+    let (entries, next) = read_ifd_entries(f, ifd_off, le, big)?;
+    let mut exif_off = None;
+    for entry in &entries {
+        match entry.tag {
+            t if t == icc_tag => {
+                let b = entry.bytes(f)?;
+                if !b.is_empty() {
+                    meta.icc = Some(b);
                 }
-                if bytes_len <= 4 {
-                    Ok(ent[8..8 + (bytes_len as usize)].to_vec())
-                } else {
-                    let mut v = vec![0u8; bytes_len as usize];
-                    read_exact_at(f, value_or_off, &mut v)?;
-                    Ok(v)
+            }
+            t if t == xres_tag => {
+                if let Some(r) = rational(&entry.bytes(f)?) {
+                    meta.x_res = Some(r);
                 }
-            };
-
-            match tag {
-                t if t == icc_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if !b.is_empty() {
-                        meta.icc = Some(b);
-                    }
+            }
+            t if t == yres_tag => {
+                if let Some(r) = rational(&entry.bytes(f)?) {
+                    meta.y_res = Some(r);
                 }
-                t if t == xres_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 8 {
-                        let n = read_u32_endian([b[0], b[1], b[2], b[3]], le);
-                        let d = read_u32_endian([b[4], b[5], b[6], b[7]], le);
-                        if d != 0 {
-                            meta.x_res = Some(Rational { n, d });
-                        }
-                    }
+            }
+            t if t == unit_tag => {
+                let b = entry.bytes(f)?;
+                if b.len() >= 2 {
+                    let u = read_u16_endian([b[0], b[1]], le);
+                    meta.unit = Some(match u {
+                        2 => ResolutionUnit::Inch,
+                        3 => ResolutionUnit::Centimeter,
+                        _ => ResolutionUnit::None,
+                    });
                 }
-                t if t == yres_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 8 {
-                        let n = read_u32_endian([b[0], b[1], b[2], b[3]], le);
-                        let d = read_u32_endian([b[4], b[5], b[6], b[7]], le);
-                        if d != 0 {
-                            meta.y_res = Some(Rational { n, d });
-                        }
-                    }
+            }
+            t if t == orientation_tag => {
+                let b = entry.bytes(f)?;
+                if b.len() >= 2 {
+                    meta.orientation = valid_orientation(read_u16_endian([b[0], b[1]], le) as u32);
                 }
-                t if t == unit_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 2 {
-                        let u = read_u16_endian([b[0], b[1]], le);
-                        meta.unit = Some(match u {
-                            2 => ResolutionUnit::Inch,
-                            3 => ResolutionUnit::Centimeter,
-                            _ => ResolutionUnit::None,
-                        });
-                    }
+            }
+            // Bit 0: reduced-resolution version of another image
+            t if t == new_subfile_tag => {
+                let b = entry.bytes(f)?;
+                if b.len() >= 4 {
+                    reduced = read_u32_endian([b[0], b[1], b[2], b[3]], le) & 1 != 0;
+                }
+            }
+            // Old-style SubfileType: 2 = reduced-resolution image
+            t if t == subfile_tag => {
+                let b = entry.bytes(f)?;
+                if b.len() >= 2 {
+                    reduced = read_u16_endian([b[0], b[1]], le) == 2;
                 }
-                _ => {}
             }
+            t if t == exif_ifd_tag => exif_off = ifd_offset(&entry.bytes(f)?, le),
+            _ => {}
         }
-    } else if magic == 43 {
-        // BigTIFF
-        let off_size = read_u16_endian([head[4], head[5]], le);
-        if off_size != 8 {
-            bail!("Unsupported BigTIFF offset size: {}", off_size);
-        }
-        let ifd0_off = read_u64_endian(
-            [
-                head[8], head[9], head[10], head[11], head[12], head[13], head[14], head[15],
-            ],
-            le,
-        );
-
-        let mut nbuf = [0u8; 8];
-        read_exact_at(&mut f, ifd0_off, &mut nbuf)?;
-        let n = read_u64_endian(nbuf, le);
-
-        let mut ent_off = ifd0_off + 8;
-        for _ in 0..n {
-            let mut ent = [0u8; 20];
-            read_exact_at(&mut f, ent_off, &mut ent)?;
-            ent_off += 20;
-
-            let tag = read_u16_endian([ent[0], ent[1]], le);
-            let ty = read_u16_endian([ent[2], ent[3]], le);
-            let count = read_u64_endian(
-                [ent[4], ent[5], ent[6], ent[7], ent[8], ent[9], ent[10], ent[11]],
-                le,
-            );
-            let value_or_off = read_u64_endian(
-                [ent[12], ent[13], ent[14], ent[15], ent[16], ent[17], ent[18], ent[19]],
-                le,
-            );
-
-            let tsz = match type_size(ty) {
-                Some(s) => s,
-                None => continue,
-            };
-            let bytes_len = count.saturating_mul(tsz);
+    }
 
-            let get_bytes = |f: &mut File| -> Result<Vec<u8>> {
-                if bytes_len == 0 {
-                    return Ok(Vec::new());
-                }
-                if bytes_len <= 8 {
-                    Ok(ent[12..12 + (bytes_len as usize)].to_vec())
-                } else {
-                    let mut v = vec![0u8; bytes_len as usize];
-                    read_exact_at(f, value_or_off, &mut v)?;
-                    Ok(v)
-                }
-            };
+    // A broken EXIF IFD does not make the page unreadable
+    if let Some(off) = exif_off
+        && let Err(e) = read_exif_ifd(f, off, le, big, &mut meta)
+    {
+        eprintln!("Warning: could not read the EXIF IFD of TIFF IFD {ifd}: {e}");
+    }
+    Ok((meta, reduced, next))
+}
 
-            match tag {
-                t if t == icc_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if !b.is_empty() {
-                        meta.icc = Some(b);
-                    }
-                }
-                t if t == xres_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 8 {
-                        let n = read_u32_endian([b[0], b[1], b[2], b[3]], le);
-                        let d = read_u32_endian([b[4], b[5], b[6], b[7]], le);
-                        if d != 0 {
-                            meta.x_res = Some(Rational { n, d });
-                        }
-                    }
-                }
-                t if t == yres_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 8 {
-                        let n = read_u32_endian([b[0], b[1], b[2], b[3]], le);
-                        let d = read_u32_endian([b[4], b[5], b[6], b[7]], le);
-                        if d != 0 {
-                            meta.y_res = Some(Rational { n, d });
-                        }
-                    }
-                }
-                t if t == unit_tag => {
-                    let b = get_bytes(&mut f)?;
-                    if b.len() >= 2 {
-                        let u = read_u16_endian([b[0], b[1]], le);
-                        meta.unit = Some(match u {
-                            2 => ResolutionUnit::Inch,
-                            3 => ResolutionUnit::Centimeter,
-                            _ => ResolutionUnit::None,
-                        });
-                    }
+/// ColorSpace from the EXIF IFD at `off`, InteroperabilityIndex from its
+/// Interoperability IFD.
+fn read_exif_ifd(f: &mut File, off: u64, le: bool, big: bool, meta: &mut TiffMeta) -> Result<()> {
+    let colour_space_tag: u16 = 40961;
+    let interop_ifd_tag: u16 = 40965;
+    let interop_index_tag: u16 = 1;
+
+    let (entries, _) = read_ifd_entries(f, off, le, big)?;
+    let mut interop_off = None;
+    for entry in &entries {
+        match entry.tag {
+            t if t == colour_space_tag => {
+                let b = entry.bytes(f)?;
+                if b.len() >= 2 {
+                    meta.exif_colour_space = Some(read_u16_endian([b[0], b[1]], le));
                 }
-                _ => {}
             }
+            t if t == interop_ifd_tag => interop_off = ifd_offset(&entry.bytes(f)?, le),
+            _ => {}
         }
-    } else {
-        bail!("Unknown TIFF magic: {}", magic);
     }
 
-    Ok(meta)
+    if let Some(off) = interop_off {
+        let (entries, _) = read_ifd_entries(f, off, le, big)?;
+        if let Some(entry) = entries.iter().find(|e| e.tag == interop_index_tag) {
+            meta.exif_interop = Some(ascii_value(&entry.bytes(f)?));
+        }
+    }
+    Ok(())
+}
+
+/// Sub-IFD offset from a LONG/IFD (4 bytes) or LONG8/IFD8 (8 bytes) value.
+fn ifd_offset(b: &[u8], le: bool) -> Option<u64> {
+    let off = match b.len() {
+        4 => read_u32_endian([b[0], b[1], b[2], b[3]], le) as u64,
+        8 => read_u64_endian([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]], le),
+        _ => return None,
+    };
+    (off != 0).then_some(off)
 }
 
-// ---------------- ICC detection helpers (TIFF/JPEG) ----------------
+// ---------------- ICC detection helpers (TIFF/JPEG/PNG) ----------------
 
-/// Read embedded ICC from JPEG APP2 ICC_PROFILE segments (minimal parser).
-fn read_icc_from_jpeg(path: &Path) -> Result<Option<Vec<u8>>> {
-    let mut data = Vec::new();
-    fs::File::open(path)?.read_to_end(&mut data)?;
+/// Read embedded ICC from a PNG iCCP chunk.
+fn read_icc_from_png(path: &Path) -> Result<Option<Vec<u8>>> {
+    use image::ImageDecoder;
+    let f = File::open(path).with_context(|| format!("Open {}", path.display()))?;
+    let mut dec = image::codecs::png::PngDecoder::new(BufReader::new(f))
+        .with_context(|| format!("Read PNG header of {}", path.display()))?;
+    Ok(dec.icc_profile()?)
+}
 
+/// Marker segments of a JPEG file up to the first scan (minimal parser).
+fn jpeg_segments(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
     if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
-        return Ok(None);
+        return segments;
     }
 
-    let mut chunks: Vec<(u8, Vec<u8>)> = Vec::new();
     let mut i = 2;
 
     while i + 4 <= data.len() {
@@ -554,16 +1201,24 @@ fn read_icc_from_jpeg(path: &Path) -> Result<Option<Vec<u8>>> {
             break;
         }
 
-        let seg = &data[i..i + (seg_len - 2)];
+        segments.push((marker, &data[i..i + (seg_len - 2)]));
         i += seg_len - 2;
+    }
+    segments
+}
 
-        if marker == 0xE2 {
-            const MAGIC: &[u8] = b"ICC_PROFILE\0";
-            if seg.len() > MAGIC.len() + 2 && &seg[..MAGIC.len()] == MAGIC {
-                let seq_no = seg[MAGIC.len()];
-                let payload = seg[MAGIC.len() + 2..].to_vec();
-                chunks.push((seq_no, payload));
-            }
+/// Read embedded ICC from JPEG APP2 ICC_PROFILE segments.
+fn read_icc_from_jpeg(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut data)?;
+
+    const MAGIC: &[u8] = b"ICC_PROFILE\0";
+    let mut chunks: Vec<(u8, Vec<u8>)> = Vec::new();
+    for (marker, seg) in jpeg_segments(&data) {
+        if marker == 0xE2 && seg.len() > MAGIC.len() + 2 && &seg[..MAGIC.len()] == MAGIC {
+            let seq_no = seg[MAGIC.len()];
+            let payload = seg[MAGIC.len() + 2..].to_vec();
+            chunks.push((seq_no, payload));
         }
     }
 
@@ -579,63 +1234,175 @@ fn read_icc_from_jpeg(path: &Path) -> Result<Option<Vec<u8>>> {
     Ok(if out.is_empty() { None } else { Some(out) })
 }
 
-fn pick_input_profile(args: &Args, tiff_meta: Option<&TiffMeta>) -> Result<Profile> {
+/// Where the input profile came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum InputIccSource {
+    /// Embedded in the image
+    Embedded,
+    /// Embedded profile replaced per --icc-map
+    Substituted,
+    /// Chosen on the command line (--detect-input-icc srgb|file)
+    Explicit,
+    /// No embedded profile; colour space taken from EXIF
+    ExifHint,
+    /// No embedded profile; sRGB assumed
+    AssumedSrgb,
+    /// JP2/JPX enumerated colour space
+    Enumerated,
+}
+
+fn pick_input_profile(
+    args: &Args,
+    catalog: &IccCatalog,
+    input: &Path,
+    tiff_meta: Option<&TiffMeta>,
+) -> Result<Option<(Profile, InputIccSource)>> {
     match args.detect_input_icc {
-        DetectInputIcc::Srgb => Ok(Profile::new_srgb()),
+        DetectInputIcc::Srgb => Ok(Some((icc::srgb(args.chromatic_adaptation)?, InputIccSource::Explicit))),
         DetectInputIcc::File => {
             let p = args
                 .input_icc_file
                 .as_deref()
                 .context("--detect-input-icc=file requires --input-icc-file")?;
-            Ok(Profile::new_file(p)?)
+            Ok(Some((icc::load_profile(p, args.chromatic_adaptation)?, InputIccSource::Explicit)))
         }
         DetectInputIcc::Auto => {
-            let ext = file_ext_lower(&args.input);
+            let ext = file_ext_lower(input);
 
-            // Prefer TIFF meta if available (cheap, no full decode)
-            if ext == "tif" || ext == "tiff" {
-                if let Some(m) = tiff_meta {
-                    if let Some(bytes) = &m.icc {
-                        return Ok(Profile::new_icc(bytes)?);
-                    }
-                }
+            if let Some(cs) = tiff_meta.and_then(|m| m.jp2_enumerated) {
+                return jp2_enumerated_profile(args, cs).map(Some);
             }
-
-            let icc_bytes = if ext == "jpg" || ext == "jpeg" {
-                read_icc_from_jpeg(&args.input)?
+            // Prefer TIFF/JP2 meta if available (cheap, no full decode)
+            let icc_bytes = if matches!(ext.as_str(), "tif" | "tiff" | "jp2" | "jpx") {
+                tiff_meta.and_then(|m| m.icc.clone())
+            } else if ext == "jpg" || ext == "jpeg" {
+                read_icc_from_jpeg(input)?
+            } else if ext == "png" {
+                read_icc_from_png(input)?
             } else {
                 None
             };
 
-            if let Some(bytes) = icc_bytes {
-                Ok(Profile::new_icc(&bytes)?)
-            } else {
-                Ok(Profile::new_srgb())
+            match icc_bytes {
+                Some(bytes) => embedded_profile(args, catalog, input, &bytes).map(Some),
+                None => missing_profile(args, input, tiff_meta),
             }
         }
     }
 }
 
-/// Output profile policy:
-/// - UC-I: ICC OFF (unless force_out_icc)
-/// - UC-II: ICC ON (default sRGB unless out_icc specified)
-/// - MC: ICC ON:
-///     - if out_icc specified => that
-///     - else if embedded input ICC exists => preserve it (do NOT force sRGB)
-///     - else => sRGB
-fn pick_output_profile_with_policy(
-    args: &Args,
-    preset: Preset,
-    in_prof: &Profile,
-    in_icc_bytes: Option<&[u8]>,
-) -> Result<Option<Profile>> {
-    match preset {
-        Preset::NdkUcI => {
-            if args.force_out_icc {
-                let p = match args.out_icc.as_deref() {
-                    Some(path) => Profile::new_file(path)?,
-                    None => Profile::new_srgb(),
-                };
+/// Profile for a JP2/JPX enumerated colour space (`colr` method 1).
+fn jp2_enumerated_profile(args: &Args, enum_cs: u32) -> Result<(Profile, InputIccSource)> {
+    let prof = match enum_cs {
+        // sYCC is decoded to sRGB
+        16 | 18 => icc::srgb(args.chromatic_adaptation)?,
+        17 => icc::builtin("gray-srgb", args.chromatic_adaptation)?,
+        _ => bail!("Unsupported JP2 enumerated colour space {enum_cs} (supported: 16 sRGB, 17 greyscale, 18 sYCC)"),
+    };
+    Ok((prof, InputIccSource::Enumerated))
+}
+
+/// No embedded profile: use the EXIF colour space hint, otherwise apply
+/// --missing-icc. `None` means the file is to be skipped.
+fn missing_profile(args: &Args, input: &Path, meta: Option<&TiffMeta>) -> Result<Option<(Profile, InputIccSource)>> {
+    if let Some((evidence, name)) = meta.and_then(exif_colour_hint) {
+        eprintln!(
+            "[icc] {}: no embedded ICC, EXIF {evidence} -> assuming builtin:{name}",
+            input.display()
+        );
+        let prof = icc::builtin(name, args.chromatic_adaptation)?;
+        return Ok(Some((prof, InputIccSource::ExifHint)));
+    }
+
+    match args.missing_icc {
+        MissingIcc::AssumeSrgb => {
+            if args.debug_icc {
+                eprintln!("[icc] {}: no embedded ICC, assuming sRGB", input.display());
+            }
+        }
+        MissingIcc::Warn => {
+            eprintln!(
+                "Warning: {}: no embedded ICC profile and no EXIF colour space, assuming sRGB",
+                input.display()
+            );
+        }
+        MissingIcc::Error => bail!("No embedded ICC profile and no EXIF colour space (--missing-icc error)"),
+        MissingIcc::Skip => return Ok(None),
+    }
+    Ok(Some((icc::srgb(args.chromatic_adaptation)?, InputIccSource::AssumedSrgb)))
+}
+
+/// Colour space stated by EXIF for untagged files: (evidence, built-in profile name).
+///
+/// DCF: ColorSpace=1 is sRGB; Adobe RGB files carry ColorSpace=Uncalibrated
+/// with InteroperabilityIndex "R03" ("R98" = sRGB).
+fn exif_colour_hint(meta: &TiffMeta) -> Option<(&'static str, &'static str)> {
+    match (meta.exif_colour_space, meta.exif_interop.as_deref()) {
+        (Some(1), _) => Some(("ColorSpace=sRGB", "srgb")),
+        (_, Some("R03")) => Some(("InteroperabilityIndex=R03", "adobe-rgb-1998")),
+        (_, Some("R98")) => Some(("InteroperabilityIndex=R98", "srgb")),
+        _ => None,
+    }
+}
+
+/// Parse an embedded profile, replacing it when --icc-map lists it as known-bad.
+fn embedded_profile(
+    args: &Args,
+    catalog: &IccCatalog,
+    input: &Path,
+    bytes: &[u8],
+) -> Result<(Profile, InputIccSource)> {
+    let prof = Profile::new_icc(bytes);
+    let desc = prof.as_ref().ok().and_then(catalog::description);
+
+    if args.debug_icc {
+        let known = catalog
+            .lookup(bytes)
+            .map(|p| format!(", catalogue: {}", p.display()))
+            .unwrap_or_default();
+        eprintln!(
+            "[icc] {} -> embedded profile md5 {}, '{}'{known}",
+            input.display(),
+            hex(&md5::compute(bytes).0),
+            desc.as_deref().unwrap_or("(no description)")
+        );
+    }
+
+    if let Some(sub) = catalog.substitute(bytes, desc.as_deref()) {
+        eprintln!(
+            "[icc] {}: embedded profile '{}' ({}) replaced by {}",
+            input.display(),
+            desc.as_deref().unwrap_or("(no description)"),
+            sub.key,
+            sub.replacement.display()
+        );
+        let repl = icc::load_profile(sub.replacement, args.chromatic_adaptation)?;
+        return Ok((repl, InputIccSource::Substituted));
+    }
+
+    Ok((prof?, InputIccSource::Embedded))
+}
+
+/// Output profile policy:
+/// - UC-I: ICC OFF (unless force_out_icc)
+/// - UC-II: ICC ON (default sRGB unless out_icc specified)
+/// - MC: ICC ON:
+///     - if out_icc specified => that
+///     - else if embedded input ICC exists => preserve it (do NOT force sRGB)
+///     - else => sRGB
+fn pick_output_profile_with_policy(
+    args: &Args,
+    preset: Preset,
+    in_prof: &Profile,
+    in_icc_bytes: Option<&[u8]>,
+) -> Result<Option<Profile>> {
+    match preset {
+        Preset::NdkUcI => {
+            if args.force_out_icc {
+                let p = match args.out_icc.as_deref() {
+                    Some(path) => icc::load_profile(path, args.chromatic_adaptation)?,
+                    None => icc::srgb(args.chromatic_adaptation)?,
+                };
                 Ok(Some(p))
             } else {
                 Ok(None)
@@ -643,14 +1410,18 @@ fn pick_output_profile_with_policy(
         }
         Preset::NdkUcII => {
             let p = match args.out_icc.as_deref() {
-                Some(path) => Profile::new_file(path)?,
-                None => Profile::new_srgb(),
+                Some(path) => icc::load_profile(path, args.chromatic_adaptation)?,
+                None => icc::srgb(args.chromatic_adaptation)?,
             };
             Ok(Some(p))
         }
         Preset::NdkMc => {
             if let Some(path) = args.out_icc.as_deref() {
-                return Ok(Some(Profile::new_file(path)?));
+                return Ok(Some(icc::load_profile(path, args.chromatic_adaptation)?));
+            }
+            // Output is always RGB, so a gray input profile cannot be preserved
+            if in_prof.color_space() == ColorSpaceSignature::GrayData {
+                return Ok(Some(icc::srgb(args.chromatic_adaptation)?));
             }
             if let Some(b) = in_icc_bytes {
                 return Ok(Some(Profile::new_icc(b)?));
@@ -661,14 +1432,121 @@ fn pick_output_profile_with_policy(
             if let Some(bb) = b {
                 return Ok(Some(Profile::new_icc(&bb)?));
             }
-            Ok(Some(Profile::new_srgb()))
+            Ok(Some(icc::srgb(args.chromatic_adaptation)?))
+        }
+        archival => {
+            // The colour space of the archival preset, unless --out-icc says otherwise
+            let name = compliance::requirements(archival).map_or("srgb", |r| r.colour_space);
+            let p = match args.out_icc.as_deref() {
+                Some(path) => icc::load_profile(path, args.chromatic_adaptation)?,
+                None => icc::builtin(name, args.chromatic_adaptation)?,
+            };
+            Ok(Some(p))
+        }
+    }
+}
+
+/// Apply --jp2-icc to the output profile: JP2 `colr` method 2 only carries
+/// restricted (matrix/TRC) profiles.
+fn check_jp2_icc(args: &Args, out_prof: Profile, output: &Path) -> Result<Profile> {
+    let mode = args.jp2_icc.unwrap_or(if is_jp2_path(output) { Jp2Icc::Error } else { Jp2Icc::Off });
+    if matches!(mode, Jp2Icc::Off) {
+        return Ok(out_prof);
+    }
+    let issues = icc::jp2_restricted_issues(&out_prof);
+    if issues.is_empty() {
+        return Ok(out_prof);
+    }
+
+    let desc = catalog::description(&out_prof).unwrap_or_else(|| "(no description)".into());
+    let msg = format!(
+        "output profile '{desc}' is not a JP2 restricted ICC profile: {}",
+        issues.join("; ")
+    );
+    match mode {
+        Jp2Icc::Off => Ok(out_prof),
+        Jp2Icc::Warn => {
+            eprintln!("Warning: {}: {msg}", output.display());
+            Ok(out_prof)
+        }
+        Jp2Icc::Error => bail!("{msg} (use --jp2-icc approximate to substitute a matrix/TRC approximation)"),
+        Jp2Icc::Approximate => {
+            let approx = icc::matrix_trc_approximation(&out_prof)
+                .with_context(|| format!("Approximate '{desc}' by matrix/TRC"))?;
+            let (mean, max) = cms::profile_difference(&out_prof, &approx)?;
+            eprintln!(
+                "[icc] {}: {msg}; replaced by matrix/TRC approximation (dE00 mean {mean:.3}, max {max:.3})",
+                output.display()
+            );
+            Ok(approx)
         }
     }
 }
 
 // ---------------- Image decode helpers ----------------
 
-fn load_rgb16(path: &Path) -> Result<(u32, u32, Vec<RGB16>)> {
+/// Decoded RGB pixels at the depth used for the ICC transform.
+#[derive(Clone)]
+enum Pixels {
+    Rgb8(Vec<RGB8>),
+    Rgb16(Vec<RGB16>),
+    /// Scene-referred or unbounded data; values outside 0..1 are kept.
+    Rgb32F(Vec<RGB<f32>>),
+}
+
+/// A decoded image.
+#[derive(Clone)]
+struct Decoded {
+    w: u32,
+    h: u32,
+    pixels: Pixels,
+    /// The source has one colour channel (decoded with R = G = B).
+    gray: bool,
+}
+
+/// Pixels of a `w`×`h` image displayed with Orientation `o` (2..=8), row by row.
+fn reorient<T: Copy + Default + Send + Sync>(w: u32, h: u32, src: &[T], o: u16) -> Vec<T> {
+    let (w, h) = (w as usize, h as usize);
+    let out_w = if o >= 5 { h } else { w };
+    let mut out = vec![T::default(); src.len()];
+    out.par_chunks_mut(out_w.max(1)).enumerate().for_each(|(y, row)| {
+        for (x, p) in row.iter_mut().enumerate() {
+            // Source pixel (column, row) for output pixel (x, y)
+            let (sx, sy) = match o {
+                2 => (w - 1 - x, y),
+                3 => (w - 1 - x, h - 1 - y),
+                4 => (x, h - 1 - y),
+                5 => (y, x),
+                6 => (y, h - 1 - x),
+                7 => (w - 1 - y, h - 1 - x),
+                8 => (w - 1 - y, x),
+                _ => (x, y),
+            };
+            *p = src[sy * w + sx];
+        }
+    });
+    out
+}
+
+/// Decode an image with `load_rgb`; TIFF pages after IFD0 are decoded with
+/// `load_tiff_page`, JP2/JPX are decoded by `jp2::decode`.
+fn load_image(path: &Path, ifd: usize, keep_8bit: bool, want_float: bool) -> Result<Decoded> {
+    if ifd > 0 {
+        return load_tiff_page(path, ifd, keep_8bit, want_float);
+    }
+    if !is_jp2_path(path) {
+        return load_rgb(path, keep_8bit, want_float);
+    }
+    let img = jp2::decode(path).with_context(|| format!("Decode JPEG 2000 {}", path.display()))?;
+    Ok(pixels_from_image(img, keep_8bit, want_float))
+}
+
+/// Decode an image to RGB (alpha dropped).
+///
+/// Float sources (and any source when `want_float` is set) decode to RGB32F.
+/// 8-bit sources stay 8-bit when `keep_8bit` is set; everything else is
+/// widened to RGB16.
+fn load_rgb(path: &Path, keep_8bit: bool, want_float: bool) -> Result<Decoded> {
     // Disable image crate decoding limits (huge TIFFs)
     let mut reader = image::ImageReader::open(path)
         .with_context(|| format!("Open input: {}", path.display()))?
@@ -677,119 +1555,167 @@ fn load_rgb16(path: &Path) -> Result<(u32, u32, Vec<RGB16>)> {
     reader.no_limits();
 
     let img = reader.decode().context("Decode image")?;
+    Ok(pixels_from_image(img, keep_8bit, want_float))
+}
+
+/// Decode IFD `ifd` of a TIFF (the image crate only reads the first image).
+fn load_tiff_page(path: &Path, ifd: usize, keep_8bit: bool, want_float: bool) -> Result<Decoded> {
+    use image::{DynamicImage, ImageBuffer};
+    use tiff::decoder::{Decoder, DecodingResult, Limits};
+    use tiff::ColorType;
+
+    let f = File::open(path).with_context(|| format!("Open input: {}", path.display()))?;
+    let mut dec = Decoder::new(BufReader::new(f))
+        .context("Read TIFF header")?
+        .with_limits(Limits::unlimited());
+    dec.seek_to_image(ifd)
+        .with_context(|| format!("Seek to TIFF IFD {ifd}"))?;
+    let (w, h) = dec.dimensions()?;
+    let color = dec.colortype()?;
+    let data = dec.read_image().with_context(|| format!("Decode TIFF IFD {ifd}"))?;
+
+    let img = match (color, data) {
+        (ColorType::Gray(8), DecodingResult::U8(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLuma8),
+        (ColorType::Gray(16), DecodingResult::U16(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLuma16),
+        (ColorType::GrayA(8), DecodingResult::U8(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLumaA8),
+        (ColorType::GrayA(16), DecodingResult::U16(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageLumaA16),
+        (ColorType::RGB(8), DecodingResult::U8(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgb8),
+        (ColorType::RGB(16), DecodingResult::U16(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgb16),
+        (ColorType::RGB(32), DecodingResult::F32(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgb32F),
+        (ColorType::RGBA(8), DecodingResult::U8(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgba8),
+        (ColorType::RGBA(16), DecodingResult::U16(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgba16),
+        (ColorType::RGBA(32), DecodingResult::F32(v)) => ImageBuffer::from_raw(w, h, v).map(DynamicImage::ImageRgba32F),
+        (color, _) => bail!("Unsupported TIFF page format {color:?} (IFD {ifd})"),
+    }
+    .ok_or_else(|| anyhow!("TIFF IFD {ifd}: sample count does not match {w}x{h} {color:?}"))?;
+    Ok(pixels_from_image(img, keep_8bit, want_float))
+}
+
+fn pixels_from_image(img: image::DynamicImage, keep_8bit: bool, want_float: bool) -> Decoded {
     let (w, h) = img.dimensions();
+    let color = img.color();
+    let gray = !color.has_color();
+    let is_8bit = color.bytes_per_pixel() == color.channel_count();
+    let is_float = matches!(
+        color,
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    );
+
+    if want_float || is_float {
+        let raw = img.into_rgb32f().into_raw();
+        let pix = raw
+            .chunks_exact(3)
+            .map(|c| RGB::new(c[0], c[1], c[2]))
+            .collect::<Vec<_>>();
+        return Decoded { w, h, pixels: Pixels::Rgb32F(pix), gray };
+    }
+
+    if keep_8bit && is_8bit {
+        let raw = img.into_rgb8().into_raw();
+        let pix = raw
+            .chunks_exact(3)
+            .map(|c| RGB8::new(c[0], c[1], c[2]))
+            .collect::<Vec<_>>();
+        return Decoded { w, h, pixels: Pixels::Rgb8(pix), gray };
+    }
 
     // Convert to RGB16
-    let raw = img.to_rgb16().into_raw();
+    let raw = img.into_rgb16().into_raw();
     let pix = raw
         .chunks_exact(3)
         .map(|c| RGB16::new(c[0], c[1], c[2]))
         .collect::<Vec<_>>();
 
-    Ok((w, h, pix))
+    Decoded { w, h, pixels: Pixels::Rgb16(pix), gray }
 }
 
-// ---------------- Quantization + tonemapping + dithering ----------------
-
-#[inline]
-fn apply_tonemap_norm(x: f32, tone: ToneMap) -> f32 {
-    let x = x.clamp(0.0, 1.0);
-    match tone {
-        ToneMap::None => x,
-        ToneMap::Gamma => x.powf(1.0 / 2.2),
-        ToneMap::Perceptual => x.sqrt(),
+impl Pixels {
+    /// Rotate/flip upright per EXIF/TIFF Orientation `o`; returns the new size.
+    fn reoriented(self, w: u32, h: u32, o: u16) -> (u32, u32, Pixels) {
+        let (w2, h2) = if o >= 5 { (h, w) } else { (w, h) };
+        let pixels = match self {
+            Pixels::Rgb8(pix) => Pixels::Rgb8(reorient(w, h, &pix, o)),
+            Pixels::Rgb16(pix) => Pixels::Rgb16(reorient(w, h, &pix, o)),
+            Pixels::Rgb32F(pix) => Pixels::Rgb32F(reorient(w, h, &pix, o)),
+        };
+        (w2, h2, pixels)
     }
-}
 
-fn quantize_rgb16_to_rgb8_stream_dither(
-    pix: &[RGB16],
-    w: u32,
-    h: u32,
-    tone: ToneMap,
-    dither: bool,
-) -> Vec<RGB8> {
-    let w = w as usize;
-    let h = h as usize;
-
-    let mut out = vec![RGB8::new(0, 0, 0); w * h];
-
-    if !dither {
-        for i in 0..(w * h) {
-            let p = pix[i];
-            let r = (apply_tonemap_norm(p.r as f32 / 65535.0, tone) * 255.0 + 0.5) as i32;
-            let g = (apply_tonemap_norm(p.g as f32 / 65535.0, tone) * 255.0 + 0.5) as i32;
-            let b = (apply_tonemap_norm(p.b as f32 / 65535.0, tone) * 255.0 + 0.5) as i32;
-            out[i] = RGB8::new(
-                r.clamp(0, 255) as u8,
-                g.clamp(0, 255) as u8,
-                b.clamp(0, 255) as u8,
-            );
+    /// Convert to RGB16 (float values are clipped to 0..1).
+    fn into_rgb16(self) -> Vec<RGB16> {
+        match self {
+            Pixels::Rgb16(pix) => pix,
+            Pixels::Rgb8(pix) => pix
+                .iter()
+                .map(|p| RGB16::new(p.r as u16 * 257, p.g as u16 * 257, p.b as u16 * 257))
+                .collect(),
+            Pixels::Rgb32F(pix) => {
+                let q = |v: f32| (v.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                pix.iter().map(|p| RGB16::new(q(p.r), q(p.g), q(p.b))).collect()
+            }
         }
-        return out;
     }
 
-    // Floyd–Steinberg with scanline error buffers:
-    // store errors as i32 in 1/16 units, per channel.
-    let mut err_cur = vec![0i32; w * 3];
-    let mut err_nxt = vec![0i32; w * 3];
-
-    for y in 0..h {
-        err_nxt.fill(0);
-
-        for x in 0..w {
-            let idx = y * w + x;
-            let p = pix[idx];
-
-            let base_r = (apply_tonemap_norm(p.r as f32 / 65535.0, tone) * 255.0).round() as i32;
-            let base_g = (apply_tonemap_norm(p.g as f32 / 65535.0, tone) * 255.0).round() as i32;
-            let base_b = (apply_tonemap_norm(p.b as f32 / 65535.0, tone) * 255.0).round() as i32;
-
-            let eoff = x * 3;
-
-            let rr = base_r + (err_cur[eoff + 0] / 16);
-            let gg = base_g + (err_cur[eoff + 1] / 16);
-            let bb = base_b + (err_cur[eoff + 2] / 16);
-
-            let qr = rr.clamp(0, 255);
-            let qg = gg.clamp(0, 255);
-            let qb = bb.clamp(0, 255);
-
-            out[idx] = RGB8::new(qr as u8, qg as u8, qb as u8);
+    /// Convert to RGB32F (integer data normalised to 0..1).
+    fn into_rgb32f(self) -> Vec<RGB<f32>> {
+        match self {
+            Pixels::Rgb32F(pix) => pix,
+            pixels => pixels.to_rgb32f(),
+        }
+    }
 
-            // quantization error (scaled *16)
-            let er = (rr - qr) * 16;
-            let eg = (gg - qg) * 16;
-            let eb = (bb - qb) * 16;
+    /// RGB32F copy (integer data normalised to 0..1).
+    fn to_rgb32f(&self) -> Vec<RGB<f32>> {
+        match self {
+            Pixels::Rgb32F(pix) => pix.clone(),
+            Pixels::Rgb16(pix) => {
+                let f = |v: u16| v as f32 / 65535.0;
+                pix.iter().map(|p| RGB::new(f(p.r), f(p.g), f(p.b))).collect()
+            }
+            Pixels::Rgb8(pix) => {
+                let f = |v: u8| v as f32 / 255.0;
+                pix.iter().map(|p| RGB::new(f(p.r), f(p.g), f(p.b))).collect()
+            }
+        }
+    }
 
-            // distribute: right (7/16), down-left (3/16), down (5/16), down-right (1/16)
-            if x + 1 < w {
-                err_cur[(x + 1) * 3 + 0] += (er * 7) / 16;
-                err_cur[(x + 1) * 3 + 1] += (eg * 7) / 16;
-                err_cur[(x + 1) * 3 + 2] += (eb * 7) / 16;
+    /// MD5 of the samples (16-bit and float samples as little-endian bytes).
+    fn md5(&self) -> [u8; 16] {
+        let mut ctx = md5::Context::new();
+        match self {
+            Pixels::Rgb8(pix) => {
+                for chunk in pix.chunks(1 << 16) {
+                    let bytes: Vec<u8> = chunk.iter().flat_map(|p| [p.r, p.g, p.b]).collect();
+                    ctx.consume(&bytes);
+                }
             }
-            if y + 1 < h {
-                if x > 0 {
-                    err_nxt[(x - 1) * 3 + 0] += (er * 3) / 16;
-                    err_nxt[(x - 1) * 3 + 1] += (eg * 3) / 16;
-                    err_nxt[(x - 1) * 3 + 2] += (eb * 3) / 16;
+            Pixels::Rgb16(pix) => {
+                for chunk in pix.chunks(1 << 16) {
+                    let bytes: Vec<u8> = chunk
+                        .iter()
+                        .flat_map(|p| [p.r, p.g, p.b])
+                        .flat_map(u16::to_le_bytes)
+                        .collect();
+                    ctx.consume(&bytes);
                 }
-                err_nxt[x * 3 + 0] += (er * 5) / 16;
-                err_nxt[x * 3 + 1] += (eg * 5) / 16;
-                err_nxt[x * 3 + 2] += (eb * 5) / 16;
-
-                if x + 1 < w {
-                    err_nxt[(x + 1) * 3 + 0] += (er * 1) / 16;
-                    err_nxt[(x + 1) * 3 + 1] += (eg * 1) / 16;
-                    err_nxt[(x + 1) * 3 + 2] += (eb * 1) / 16;
+            }
+            Pixels::Rgb32F(pix) => {
+                for chunk in pix.chunks(1 << 16) {
+                    let bytes: Vec<u8> = chunk
+                        .iter()
+                        .flat_map(|p| [p.r, p.g, p.b])
+                        .flat_map(f32::to_le_bytes)
+                        .collect();
+                    ctx.consume(&bytes);
                 }
             }
         }
-
-        std::mem::swap(&mut err_cur, &mut err_nxt);
-        err_cur.fill(0);
+        ctx.finalize().0
     }
+}
 
-    out
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
 // ---------------- TIFF writing with ICC + DPI ----------------
@@ -816,67 +1742,59 @@ fn rat(v: &Rational) -> Rational {
     Rational { n: v.n, d: v.d }
 }
 
-fn normalize_resolution(meta: Option<&TiffMeta>) -> (ResolutionUnit, Rational, Rational) {
-    // Defaults if nothing known:
-    let mut unit = ResolutionUnit::Inch;
-    let mut xr = Rational { n: 600, d: 1 };
-    let mut yr = Rational { n: 600, d: 1 };
-
-    if let Some(m) = meta {
-        if let Some(u) = m.unit {
-            // If input says None but has values, we still prefer Inch for "dpi" semantics.
-            unit = if matches!(u, ResolutionUnit::None) {
-                ResolutionUnit::Inch
-            } else {
-                u
-            };
-        }
+/// Resolution to write, or `None` when the input states none (nothing is
+/// invented; see --missing-resolution).
+fn normalize_resolution(meta: Option<&TiffMeta>) -> Option<(ResolutionUnit, Rational, Rational)> {
+    let m = meta?;
+    let valid = |r: &Option<Rational>| r.as_ref().filter(|r| r.n > 0 && r.d > 0).map(rat);
+
+    // If one axis is missing, mirror the other
+    let (xr, yr) = match (valid(&m.x_res), valid(&m.y_res)) {
+        (Some(x), Some(y)) => (x, y),
+        (Some(x), None) => (rat(&x), x),
+        (None, Some(y)) => (rat(&y), y),
+        (None, None) => return None,
+    };
 
-        if let Some(x) = m.x_res.as_ref() {
-            xr = rat(x);
-        }
-        if let Some(y) = m.y_res.as_ref() {
-            yr = rat(y);
-        } else if m.x_res.is_some() {
-            // If y missing but x exists, mirror x
-            yr = Rational { n: xr.n, d: xr.d };
-        }
+    // ResolutionUnit=None only gives an aspect ratio (written by the TIFF
+    // encoder when no resolution is known); a missing unit defaults to inch.
+    let unit = match m.unit {
+        Some(ResolutionUnit::None) => return None,
+        Some(ResolutionUnit::Centimeter) => ResolutionUnit::Centimeter,
+        _ => ResolutionUnit::Inch,
+    };
+    Some((unit, xr, yr))
+}
 
-        // If x missing but y exists, mirror y
-        if m.x_res.is_none() && m.y_res.is_some() {
-            xr = Rational { n: yr.n, d: yr.d };
-        }
-    }
+/// Whether `meta` states a usable resolution.
+fn has_resolution(meta: Option<&TiffMeta>) -> bool {
+    normalize_resolution(meta).is_some()
+}
 
-    // Avoid nonsense denom=0
-    if xr.d == 0 {
-        xr = Rational { n: 600, d: 1 };
-    }
-    if yr.d == 0 {
-        yr = Rational { n: 600, d: 1 };
-    }
+type TiffWriter = TiffEncoder<BufWriter<File>>;
 
-    (unit, xr, yr)
+fn create_tiff(out_path: &Path) -> Result<TiffWriter> {
+    let f = File::create(out_path).with_context(|| format!("Create output: {}", out_path.display()))?;
+    Ok(TiffEncoder::new(BufWriter::new(f))?)
 }
 
+/// Append an RGB16 image (page) to `tiff`.
 fn write_tiff_rgb16(
-    out_path: &Path,
+    tiff: &mut TiffWriter,
     w: u32,
     h: u32,
     pix: &[RGB16],
     icc: Option<&[u8]>,
     meta: Option<&TiffMeta>,
 ) -> Result<()> {
-    let f = File::create(out_path).with_context(|| format!("Create output: {}", out_path.display()))?;
-    let mut tiff = TiffEncoder::new(BufWriter::new(f))?;
-
     let mut img = tiff.new_image::<colortype::RGB16>(w, h)?;
 
     // Resolution tags
-    let (unit, xr, yr) = normalize_resolution(meta);
-    img.resolution_unit(unit);
-    img.x_resolution(xr);
-    img.y_resolution(yr);
+    if let Some((unit, xr, yr)) = normalize_resolution(meta) {
+        img.resolution_unit(unit);
+        img.x_resolution(xr);
+        img.y_resolution(yr);
+    }
 
     // Embed ICC into TIFF (tag 34675) as UNDEFINED (7)
     if let Some(icc_bytes) = icc {
@@ -884,6 +1802,11 @@ fn write_tiff_rgb16(
             .write_tag(Tag::Unknown(34675), UndefinedBytes(icc_bytes))
             .context("Write ICCProfile tag (34675) as UNDEFINED")?;
     }
+    if let Some(o) = meta.and_then(|m| m.orientation) {
+        img.encoder()
+            .write_tag(Tag::Orientation, o)
+            .context("Write Orientation tag (274)")?;
+    }
 
     // Stream write by strips (avoid huge raw allocation)
     img.rows_per_strip(64)?;
@@ -910,23 +1833,22 @@ fn write_tiff_rgb16(
     Ok(())
 }
 
+/// Append an RGB8 image (page) to `tiff`.
 fn write_tiff_rgb8(
-    out_path: &Path,
+    tiff: &mut TiffWriter,
     w: u32,
     h: u32,
     pix: &[RGB8],
     icc: Option<&[u8]>,
     meta: Option<&TiffMeta>,
 ) -> Result<()> {
-    let f = File::create(out_path).with_context(|| format!("Create output: {}", out_path.display()))?;
-    let mut tiff = TiffEncoder::new(BufWriter::new(f))?;
-
     let mut img = tiff.new_image::<colortype::RGB8>(w, h)?;
 
-    let (unit, xr, yr) = normalize_resolution(meta);
-    img.resolution_unit(unit);
-    img.x_resolution(xr);
-    img.y_resolution(yr);
+    if let Some((unit, xr, yr)) = normalize_resolution(meta) {
+        img.resolution_unit(unit);
+        img.x_resolution(xr);
+        img.y_resolution(yr);
+    }
 
     // Embed ICC into TIFF (tag 34675) as UNDEFINED (7)
     if let Some(icc_bytes) = icc {
@@ -934,6 +1856,11 @@ fn write_tiff_rgb8(
             .write_tag(Tag::Unknown(34675), UndefinedBytes(icc_bytes))
             .context("Write ICCProfile tag (34675) as UNDEFINED")?;
     }
+    if let Some(o) = meta.and_then(|m| m.orientation) {
+        img.encoder()
+            .write_tag(Tag::Orientation, o)
+            .context("Write Orientation tag (274)")?;
+    }
 
     img.rows_per_strip(128)?;
 
@@ -959,6 +1886,60 @@ fn write_tiff_rgb8(
     Ok(())
 }
 
+/// Append an RGB32F image (page) to `tiff`.
+fn write_tiff_rgb32f(
+    tiff: &mut TiffWriter,
+    w: u32,
+    h: u32,
+    pix: &[RGB<f32>],
+    icc: Option<&[u8]>,
+    meta: Option<&TiffMeta>,
+) -> Result<()> {
+    // RGB32Float writes BitsPerSample=32 and SampleFormat=3 (IEEEFP)
+    let mut img = tiff.new_image::<colortype::RGB32Float>(w, h)?;
+
+    if let Some((unit, xr, yr)) = normalize_resolution(meta) {
+        img.resolution_unit(unit);
+        img.x_resolution(xr);
+        img.y_resolution(yr);
+    }
+
+    // Embed ICC into TIFF (tag 34675) as UNDEFINED (7)
+    if let Some(icc_bytes) = icc {
+        img.encoder()
+            .write_tag(Tag::Unknown(34675), UndefinedBytes(icc_bytes))
+            .context("Write ICCProfile tag (34675) as UNDEFINED")?;
+    }
+    if let Some(o) = meta.and_then(|m| m.orientation) {
+        img.encoder()
+            .write_tag(Tag::Orientation, o)
+            .context("Write Orientation tag (274)")?;
+    }
+
+    img.rows_per_strip(32)?;
+
+    let mut row = 0u32;
+    while img.next_strip_sample_count() > 0 {
+        let rows = min(32, h - row);
+        let start = (row as usize) * (w as usize);
+        let end = ((row + rows) as usize) * (w as usize);
+        let slice = &pix[start..end];
+
+        let mut raw: Vec<f32> = Vec::with_capacity(slice.len() * 3);
+        for p in slice {
+            raw.push(p.r);
+            raw.push(p.g);
+            raw.push(p.b);
+        }
+
+        img.write_strip(&raw)?;
+        row += rows;
+    }
+
+    img.finish()?;
+    Ok(())
+}
+
 // ---------------- File operations ----------------
 
 fn is_supported_image_ext(p: &Path) -> bool {
@@ -967,7 +1948,7 @@ fn is_supported_image_ext(p: &Path) -> bool {
         .and_then(|s| s.to_str())
         .map(|s| s.to_ascii_lowercase())
     {
-        Some(ext) => matches!(ext.as_str(), "tif" | "tiff" | "png" | "jpg" | "jpeg"),
+        Some(ext) => matches!(ext.as_str(), "tif" | "tiff" | "png" | "jpg" | "jpeg" | "jp2" | "jpx"),
         None => false,
     }
 }
@@ -999,95 +1980,567 @@ fn collect_input_files(root: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
 
 fn normalize_out_ext(ext: &str) -> Result<String> {
     let e = ext.trim().trim_start_matches('.').to_ascii_lowercase();
-    if matches!(e.as_str(), "tif" | "tiff" | "png" | "jpg" | "jpeg") {
+    if matches!(e.as_str(), "tif" | "tiff" | "png" | "jpg" | "jpeg" | "jp2") {
         Ok(e)
     } else {
-        bail!("Unsupported --out-ext: {ext}. Use one of: tif, tiff, png, jpg, jpeg");
+        bail!("Unsupported --out-ext: {ext}. Use one of: tif, tiff, png, jpg, jpeg, jp2");
     }
 }
 
+/// Whether `output` (with --tiles: its tile set) already exists.
+fn output_exists(args: &Args, output: &Path) -> bool {
+    output.exists() || args.tiles.is_some_and(|f| tiles::descriptor_path(f, output).exists())
+}
+
 fn sidecar_path_for(output: &Path) -> PathBuf {
     let mut p = output.to_path_buf();
     p.set_extension("icc");
     p
 }
 
-fn convert_one(args: &Args, eff: &Effective, input: &Path, output: &Path) -> Result<()> {
-    let in_is_tiff = is_tiff_path(input);
-    let out_is_tiff = is_tiff_path(output);
+fn convert_one(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    output: &Path,
+) -> Result<()> {
+    if args.threads == 0 {
+        return convert_one_in_pool(args, eff, cache, catalog, input, output);
+    }
 
-    // Read TIFF meta (ICC + resolution) cheaply if input is TIFF.
-    let tiff_meta = if in_is_tiff {
-        match read_tiff_meta(input) {
-            Ok(meta) => Some(meta),
-            Err(e) => {
-                eprintln!(
-                    "Warning: could not read TIFF metadata from {}: {}",
-                    input.display(),
-                    e
-                );
-                None
+    // Per-image pool, built once by each batch worker (or the main thread) and
+    // reused for every file it converts.
+    IMAGE_POOL.with(|cell| {
+        let pool = match cell.get() {
+            Some(pool) => pool,
+            None => {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(args.threads)
+                    .build()
+                    .context("Failed to create per-image thread pool")?;
+                cell.get_or_init(|| pool)
             }
-        }
+        };
+        pool.install(|| convert_one_in_pool(args, eff, cache, catalog, input, output))
+    })
+}
+
+thread_local! {
+    /// --threads pool of the current worker thread.
+    static IMAGE_POOL: std::cell::OnceCell<rayon::ThreadPool> = const { std::cell::OnceCell::new() };
+}
+
+fn convert_one_in_pool(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    output: &Path,
+) -> Result<()> {
+    let pages = read_pages(input);
+    if !args.derivative.is_empty() {
+        return convert_derivatives(args, cache, catalog, input, &pages, output);
+    }
+    convert_pages(args, eff, cache, catalog, input, &pages, output)
+}
+
+/// Metadata of each page of `input` (TIFF/JP2: ICC + resolution, PNG/JPEG:
+/// resolution), read cheaply; one entry per full-resolution TIFF page.
+fn read_pages(input: &Path) -> Vec<Option<TiffMeta>> {
+    let meta = if is_jp2_path(input) {
+        read_jp2_meta(input).map(|m| vec![m])
+    } else if is_tiff_path(input) {
+        read_tiff_pages(input)
     } else {
-        None
+        read_raster_meta(input).map(|m| vec![m])
     };
+    match meta {
+        Ok(pages) if !pages.is_empty() => pages.into_iter().map(Some).collect(),
+        Ok(_) => vec![None],
+        Err(e) => {
+            eprintln!("Warning: could not read metadata from {}: {}", input.display(), e);
+            vec![None]
+        }
+    }
+}
 
-    let in_prof = pick_input_profile(args, tiff_meta.as_ref())
-        .with_context(|| format!("Pick input ICC profile for {}", input.display()))?;
+/// Convert all `pages` of `input` to `output` (one file, split files or tiles).
+fn convert_pages(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    pages: &[Option<TiffMeta>],
+    output: &Path,
+) -> Result<()> {
+    let out_is_tiff = is_tiff_path(output);
 
-    // Input ICC bytes (for "preserve embedded ICC" behavior)
-    let in_icc_bytes = tiff_meta.as_ref().and_then(|m| m.icc.as_deref());
+    check_float_output(args, eff, output)?;
 
-    let out_prof_opt = pick_output_profile_with_policy(args, eff.preset, &in_prof, in_icc_bytes)
-        .with_context(|| format!("Pick output ICC profile (policy) for {}", input.display()))?;
+    let pages = pages_with_policies(args, eff, input, pages)?;
+    if let Some(format) = args.tiles {
+        if pages.len() > 1 {
+            eprintln!(
+                "Warning: {}: {} further pages ignored (--tiles)",
+                input.display(),
+                pages.len() - 1
+            );
+        }
+        return convert_tiles(args, eff, cache, catalog, input, pages[0].as_ref(), output, format);
+    }
 
-    // Optional: write ICC sidecar next to each output image
-    if args.write_icc {
-        if let Some(out_prof) = out_prof_opt.as_ref() {
-            match out_prof.icc() {
-                Ok(out_bytes) => {
-                    let path = sidecar_path_for(output);
-                    fs::write(&path, out_bytes)
-                        .with_context(|| format!("Write ICC sidecar to {}", path.display()))?;
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: could not export ICC for sidecar {}: {}",
-                        output.display(),
-                        e
-                    );
-                }
-            }
+    let mode = match args.pages {
+        Pages::Auto if out_is_tiff => Pages::Multipage,
+        Pages::Auto => Pages::Split,
+        mode => mode,
+    };
+    if pages.len() == 1 || mode == Pages::First {
+        if pages.len() > 1 {
+            eprintln!(
+                "Warning: {}: {} further pages ignored (--pages first)",
+                input.display(),
+                pages.len() - 1
+            );
         }
+        return convert_single(args, eff, cache, catalog, input, pages[0].as_ref(), output, None);
     }
 
-    // Debug info - print for each file
     if args.debug_icc {
-        match in_prof.icc() {
-            Ok(in_bytes) => {
-                eprintln!(
-                    "[icc] {} -> in_profile: {} bytes (v{:.4})",
-                    input.display(),
-                    in_bytes.len(),
-                    in_prof.version()
-                );
-            }
-            Err(e) => {
-                eprintln!(
-                    "[icc] {} -> failed to get input ICC: {}",
-                    input.display(),
-                    e
-                );
-            }
+        eprintln!("[pages] {}: {} pages, {:?}", input.display(), pages.len(), mode);
+    }
+    if mode == Pages::Multipage {
+        if !out_is_tiff {
+            bail!("--pages multipage requires a TIFF output: {}", output.display());
         }
+        return convert_multipage(args, eff, cache, catalog, input, &pages, output);
+    }
 
-        if let Some(out_prof) = out_prof_opt.as_ref() {
-            match out_prof.icc() {
-                Ok(out_bytes) => {
-                    eprintln!(
-                        "[icc] {} -> out_profile: {} bytes (v{:.4})",
-                        output.display(),
+    for (n, meta) in pages.iter().enumerate() {
+        let page_output = page_output_path(output, n + 1);
+        if page_output.exists() && !args.overwrite {
+            eprintln!("Skipping existing: {}", page_output.display());
+            continue;
+        }
+        convert_single(args, eff, cache, catalog, input, meta.as_ref(), &page_output, None)
+            .with_context(|| format!("Page {} -> {}", n + 1, page_output.display()))?;
+    }
+    Ok(())
+}
+
+/// PNG/JPEG have no float samples; fail before the (possibly huge) decode.
+fn check_float_output(args: &Args, eff: &Effective, output: &Path) -> Result<()> {
+    if matches!(eff.out_depth, BitDepth::B32f) && !is_tiff_path(output) && args.tiles.is_none() {
+        bail!(
+            "32-bit float output (--out-depth b32f) requires a TIFF output: {}",
+            output.display()
+        );
+    }
+    Ok(())
+}
+
+/// `pages` with the --missing-resolution and --orientation policies of `eff` applied.
+fn pages_with_policies(
+    args: &Args,
+    eff: &Effective,
+    input: &Path,
+    pages: &[Option<TiffMeta>],
+) -> Result<Vec<Option<TiffMeta>>> {
+    let mut pages = pages.to_vec();
+    for (n, meta) in pages.iter_mut().enumerate() {
+        if !has_resolution(meta.as_ref()) {
+            apply_missing_resolution(args, eff, input, n, meta)?;
+        }
+        if let Some(meta) = meta {
+            apply_orientation_policy(eff, meta);
+        }
+    }
+    Ok(pages)
+}
+
+/// Write every --derivative of `input`. A single-page input is decoded once
+/// and the pixels are shared; each derivative reports its own result.
+fn convert_derivatives(
+    args: &Args,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    pages: &[Option<TiffMeta>],
+    output: &Path,
+) -> Result<()> {
+    let mut derivatives = Vec::new();
+    let mut paths = std::collections::HashSet::new();
+    for d in &args.derivative {
+        let path = d.path(output);
+        if !paths.insert(path.clone()) {
+            bail!("Two derivatives write {}; give them distinct suffix= or ext=", path.display());
+        }
+        if path.exists() && !args.overwrite {
+            eprintln!("Skipping existing: {}", path.display());
+            continue;
+        }
+        let d_args = d.args(args);
+        let d_eff = compute_effective(&d_args);
+        derivatives.push((d_args, d_eff, path));
+    }
+    if derivatives.is_empty() {
+        return Ok(());
+    }
+
+    // One decode serves all derivatives: 8-bit only if every derivative keeps
+    // 8 bits, float if any needs float.
+    let mut decoded = None;
+    let shared = pages.len() == 1;
+    if shared {
+        let keep_8bit = derivatives.iter().all(|(a, e, _)| load_depth(a, e).0);
+        let want_float = derivatives.iter().any(|(a, e, _)| load_depth(a, e).1);
+        let ifd = pages[0].as_ref().map_or(0, |m| m.ifd);
+        decoded = Some(
+            load_image(input, ifd, keep_8bit, want_float)
+                .with_context(|| format!("Load image pixels from {}", input.display()))?,
+        );
+    } else if pages.len() > 1 && args.debug_icc {
+        eprintln!(
+            "[derivative] {}: {} pages, decoded once per derivative",
+            input.display(),
+            pages.len()
+        );
+    }
+
+    let count = derivatives.len();
+    let mut failed = 0;
+    for (n, (d_args, d_eff, path)) in derivatives.iter().enumerate() {
+        let result = if shared {
+            // The last derivative takes the decoded buffer itself
+            let pixels = if n + 1 == count { decoded.take() } else { decoded.clone() };
+            check_float_output(d_args, d_eff, path)
+                .and_then(|()| pages_with_policies(d_args, d_eff, input, pages))
+                .and_then(|mut meta| {
+                    let meta = meta.swap_remove(0);
+                    convert_single(d_args, d_eff, cache, catalog, input, meta.as_ref(), path, pixels)
+                })
+        } else {
+            convert_pages(d_args, d_eff, cache, catalog, input, pages, path)
+        };
+        match result {
+            Ok(()) => eprintln!(
+                "[derivative] {} -> {}: ok ({:?}, {:?})",
+                input.display(),
+                path.display(),
+                d_eff.preset,
+                d_eff.out_depth
+            ),
+            Err(e) => {
+                failed += 1;
+                eprintln!("[derivative] {} -> {}: FAILED: {e:#}", input.display(), path.display());
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {count} derivatives failed");
+    }
+    Ok(())
+}
+
+/// `<stem>_p0001.<ext>` for page 1 of a split multi-page input.
+fn page_output_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("page");
+    let mut name = format!("{stem}_p{number:04}");
+    if let Some(ext) = output.extension().and_then(|e| e.to_str()) {
+        name = format!("{name}.{ext}");
+    }
+    output.with_file_name(name)
+}
+
+/// Convert one image (or TIFF page) to its own output file.
+#[allow(clippy::too_many_arguments)]
+fn convert_single(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    meta: Option<&TiffMeta>,
+    output: &Path,
+    decoded: Option<Decoded>,
+) -> Result<()> {
+    let Some(page) = convert_page(args, eff, cache, catalog, input, meta, output, decoded)? else {
+        eprintln!(
+            "Skipping {}: no embedded ICC profile (--missing-icc skip)",
+            input.display()
+        );
+        return Ok(());
+    };
+
+    let (w, h, icc, meta) = (page.w, page.h, page.icc.as_deref(), page.meta.as_ref());
+    match &page.pixels {
+        _ if is_jp2_path(output) => save_jp2(eff, output, w, h, &page.pixels, icc, meta)?,
+        Pixels::Rgb32F(rgbf) => save_rgb32f(output, w, h, rgbf, icc, meta)?,
+        Pixels::Rgb16(rgb16) => save_rgb16(output, w, h, rgb16, icc, meta)?,
+        Pixels::Rgb8(rgb8) => save_rgb8(output, w, h, rgb8, icc, meta)?,
+    }
+
+    if let Some(check) = page.check {
+        drop(page.pixels);
+        verify_output_pixels(eff, output, 0, check)?;
+    }
+    Ok(())
+}
+
+/// Convert all pages of a multi-page TIFF into one multi-page TIFF output.
+fn convert_multipage(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    pages: &[Option<TiffMeta>],
+    output: &Path,
+) -> Result<()> {
+    let mut tiff: Option<TiffWriter> = None;
+    let mut checks = Vec::new();
+
+    for (n, meta) in pages.iter().enumerate() {
+        let page = convert_page(args, eff, cache, catalog, input, meta.as_ref(), output, None)
+            .with_context(|| format!("Page {}", n + 1))?;
+        let Some(page) = page else {
+            eprintln!(
+                "Skipping {} page {}: no embedded ICC profile (--missing-icc skip)",
+                input.display(),
+                n + 1
+            );
+            continue;
+        };
+        let tiff = match &mut tiff {
+            Some(tiff) => tiff,
+            None => tiff.insert(create_tiff(output)?),
+        };
+        let (w, h, icc, meta) = (page.w, page.h, page.icc.as_deref(), page.meta.as_ref());
+        match &page.pixels {
+            Pixels::Rgb32F(rgbf) => write_tiff_rgb32f(tiff, w, h, rgbf, icc, meta),
+            Pixels::Rgb16(rgb16) => write_tiff_rgb16(tiff, w, h, rgb16, icc, meta),
+            Pixels::Rgb8(rgb8) => write_tiff_rgb8(tiff, w, h, rgb8, icc, meta),
+        }
+        .with_context(|| format!("Write page {} to {}", n + 1, output.display()))?;
+        checks.push(page.check);
+    }
+
+    // Flush the file before re-reading it
+    drop(tiff);
+    for (ifd, check) in checks.into_iter().enumerate() {
+        if let Some(check) = check {
+            verify_output_pixels(eff, output, ifd, check)?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert one image (or TIFF page) to a tile pyramid at `output` (see `tiles::descriptor_path`).
+#[allow(clippy::too_many_arguments)]
+fn convert_tiles(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    meta: Option<&TiffMeta>,
+    output: &Path,
+    format: TileFormat,
+) -> Result<()> {
+    // Levels are resampled from the 16-bit conversion, then quantized per level.
+    let eff16 = Effective {
+        out_depth: BitDepth::B16,
+        verify: false,
+        ..*eff
+    };
+    let Some(page) = convert_page(args, &eff16, cache, catalog, input, meta, output, None)? else {
+        eprintln!(
+            "Skipping {}: no embedded ICC profile (--missing-icc skip)",
+            input.display()
+        );
+        return Ok(());
+    };
+    let (w, h) = (page.w, page.h);
+
+    let srgb = icc::srgb(args.chromatic_adaptation)?;
+    let icc_bytes = icc::serialize_as(&srgb, args.icc_version).context("Serialize sRGB profile for the tiles")?;
+    let opts = tiles::TileOptions {
+        size: args.tile_size.unwrap_or(format.default_tile_size()),
+        overlap: args.tile_overlap,
+        quality: args.tile_quality,
+        icc: &icc_bytes,
+    };
+    let levels = tiles::levels(format, w, h, opts.size, args.tile_scale_factors.as_deref());
+
+    // The page is linearized once; each level is resampled from the previous
+    // one (levels shrink), in linear light.
+    let light = LinearLight::new(args, cache, &srgb)?;
+    let mut linear: Option<(u32, u32, Vec<RGB<f32>>)> = None;
+    for (n, level) in levels.iter().enumerate() {
+        let (lw, lh) = (level.width, level.height);
+        let pixels = match &page.pixels {
+            Pixels::Rgb16(rgb16) if (lw, lh) == (w, h) => Cow::Borrowed(rgb16),
+            pixels => {
+                let (sw, sh, src) = linear.get_or_insert_with(|| (w, h, light.linearize(pixels.to_rgb32f())));
+                let resized = resize::resample(src, *sw, *sh, lw, lh, args.resize_filter);
+                let rgb16 = Pixels::Rgb32F(light.delinearize(&resized)).into_rgb16();
+                linear = Some((lw, lh, resized));
+                Cow::Owned(rgb16)
+            }
+        };
+        let rgb8 = quantize_rgb16_to_rgb8_stream_dither(&pixels, lw, lh, eff.tone_map, eff.dither);
+        tiles::write_level(format, output, w, h, &levels, n, &rgb8, &opts)?;
+        if args.debug_icc {
+            eprintln!("[tiles] {}: scale {} -> {lw}x{lh}", output.display(), level.scale);
+        }
+    }
+
+    tiles::write_descriptor(format, output, args.iiif_base.as_deref(), w, h, &levels, &opts)
+}
+
+/// A converted image, as it is to be written.
+struct ConvertedPage {
+    w: u32,
+    h: u32,
+    pixels: Pixels,
+    /// Output ICC to embed (TIFF/JP2 outputs)
+    icc: Option<Vec<u8>>,
+    /// Resolution and orientation to write (resolution updated by --resize)
+    meta: Option<TiffMeta>,
+    /// Set with --verify
+    check: Option<PixelCheck>,
+}
+
+/// Expected decode of an output page, for --verify.
+struct PixelCheck {
+    expected: [u8; 16],
+    keep_8bit: bool,
+    want_float: bool,
+}
+
+/// Colour-convert one image (or TIFF page, when `meta` names its IFD), from
+/// `decoded` pixels when the page was already decoded (--derivative).
+/// `None` means the image is to be skipped (--missing-icc skip).
+#[allow(clippy::too_many_arguments)]
+fn convert_page(
+    args: &Args,
+    eff: &Effective,
+    cache: &TransformCache,
+    catalog: &IccCatalog,
+    input: &Path,
+    meta: Option<&TiffMeta>,
+    output: &Path,
+    decoded: Option<Decoded>,
+) -> Result<Option<ConvertedPage>> {
+    let out_is_tiff = is_tiff_path(output);
+    let out_is_jp2 = is_jp2_path(output);
+
+    let Some((in_prof, in_source)) = pick_input_profile(args, catalog, input, meta)
+        .with_context(|| format!("Pick input ICC profile for {}", input.display()))?
+    else {
+        return Ok(None);
+    };
+
+    // Input ICC bytes (for "preserve embedded ICC" behavior); a substituted
+    // profile is preserved instead of the known-bad embedded one.
+    let substituted_bytes = match in_source {
+        InputIccSource::Substituted => Some(in_prof.icc()?),
+        _ => None,
+    };
+    let in_icc_bytes = substituted_bytes
+        .as_deref()
+        .or_else(|| meta.and_then(|m| m.icc.as_deref()));
+
+    // Tiles are always sRGB
+    let out_prof_opt = match args.tiles {
+        Some(_) => Some(icc::srgb(args.chromatic_adaptation)?),
+        None => pick_output_profile_with_policy(args, eff.preset, &in_prof, in_icc_bytes)
+            .with_context(|| format!("Pick output ICC profile (policy) for {}", input.display()))?,
+    };
+    let out_prof_opt = match out_prof_opt {
+        Some(out_prof) => Some(check_jp2_icc(args, out_prof, output)?),
+        None => None,
+    };
+
+    // Resampling in the input profile's linear light converts back to the input profile
+    if args.resize.is_some()
+        && (args.resize_space == ResizeSpace::Input || out_prof_opt.is_none())
+        && !icc::usable_as_output(&in_prof, Intent::RelativeColorimetric)
+    {
+        let desc = catalog::description(&in_prof).unwrap_or_else(|| "(no description)".into());
+        bail!(
+            "--resize needs to convert back to the input profile '{desc}', which is LUT-based without BToA tables \
+             (use --resize-space output with an output profile)"
+        );
+    }
+
+    // Output profile as written to files (sidecar, TIFF tag), in the requested ICC version
+    let mut out_icc_bytes = match out_prof_opt.as_ref() {
+        None => None,
+        Some(out_prof) if args.icc_version == IccVersion::Keep => match out_prof.icc() {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                eprintln!(
+                    "Warning: could not export output ICC bytes for {}: {}",
+                    output.display(),
+                    e
+                );
+                None
+            }
+        },
+        Some(out_prof) => Some(
+            icc::serialize_as(out_prof, args.icc_version)
+                .with_context(|| format!("Apply --icc-version to the output profile for {}", output.display()))?,
+        ),
+    };
+
+    // Optional: write ICC sidecar next to each output image
+    if args.write_icc
+        && let Some(out_bytes) = out_icc_bytes.as_ref()
+    {
+        let path = sidecar_path_for(output);
+        fs::write(&path, out_bytes)
+            .with_context(|| format!("Write ICC sidecar to {}", path.display()))?;
+    }
+
+    // Debug info - print for each file
+    if args.debug_icc {
+        match in_prof.icc() {
+            Ok(in_bytes) => {
+                eprintln!(
+                    "[icc] {} -> in_profile: {} bytes (v{:.4}), source: {:?}",
+                    input.display(),
+                    in_bytes.len(),
+                    in_prof.version(),
+                    in_source
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "[icc] {} -> failed to get input ICC: {}",
+                    input.display(),
+                    e
+                );
+            }
+        }
+
+        eprintln!(
+            "[icc] {} -> intent: {:?}, adaptation state: {}, chromatic adaptation: {}",
+            input.display(),
+            eff.intent,
+            args.adaptation_state,
+            args.chromatic_adaptation.name()
+        );
+
+        if let Some(out_prof) = out_prof_opt.as_ref() {
+            match out_prof.icc() {
+                Ok(out_bytes) => {
+                    eprintln!(
+                        "[icc] {} -> out_profile: {} bytes (v{:.4})",
+                        output.display(),
                         out_bytes.len(),
                         out_prof.version()
                     );
@@ -1100,6 +2553,17 @@ fn convert_one(args: &Args, eff: &Effective, input: &Path, output: &Path) -> Res
                     );
                 }
             }
+            if args.icc_version != IccVersion::Keep
+                && let Some(bytes) = out_icc_bytes.as_ref()
+            {
+                let written = Profile::new_icc(bytes).map(|p| p.version()).unwrap_or(0.0);
+                eprintln!(
+                    "[icc] {} -> out_profile written as ICC v{:.4}: {} bytes",
+                    output.display(),
+                    written,
+                    bytes.len()
+                );
+            }
         } else {
             eprintln!(
                 "[icc] {} -> out_profile: (NONE) per policy",
@@ -1108,152 +2572,558 @@ fn convert_one(args: &Args, eff: &Effective, input: &Path, output: &Path) -> Res
         }
     }
 
-    // Load image pixels (RGB16 path; we quantize later if needed)
-    let (w, h, mut rgb16) = load_rgb16(input)
-        .with_context(|| format!("Load image as RGB16 from {}", input.display()))?;
+    let Decoded { mut w, mut h, mut pixels, gray } = match decoded {
+        Some(decoded) => decoded,
+        None => {
+            let (keep_8bit, want_float) = load_depth(args, eff);
+            let ifd = meta.map_or(0, |m| m.ifd);
+            load_image(input, ifd, keep_8bit, want_float)
+                .with_context(|| format!("Load image pixels from {}", input.display()))?
+        }
+    };
+    // A gray profile only reads the first sample, which would drop G and B of a colour image
+    if !gray && in_prof.color_space() == ColorSpaceSignature::GrayData {
+        let desc = catalog::description(&in_prof).unwrap_or_else(|| "(no description)".into());
+        bail!(
+            "Input profile '{desc}' ({in_source:?}) is a gray profile, but {} is a colour image",
+            input.display()
+        );
+    }
+    if let Some(o) = meta.and_then(|m| m.reorient) {
+        if args.debug_icc {
+            eprintln!("[orientation] {}: applying Orientation={o}", input.display());
+        }
+        (w, h, pixels) = pixels.reoriented(w, h, o);
+    }
+
+    let mut out_meta = meta.cloned();
+    let target = match args.resize {
+        Some(spec) => resize::target_size(spec, w, h, resolution_dpi(meta))?,
+        None => None,
+    };
+    if let Some((nw, nh)) = target {
+        if args.debug_icc {
+            eprintln!(
+                "[resize] {}: {w}x{h} -> {nw}x{nh} ({:?}, {:?} space)",
+                input.display(),
+                args.resize_filter,
+                args.resize_space
+            );
+        }
+        if let Some(m) = out_meta.as_mut() {
+            resize_resolution(m, args.resize, w, h, nw, nh);
+        }
+    }
+    if let Some((nw, nh)) = target.filter(|_| args.resize_space == ResizeSpace::Input) {
+        pixels = resize_pixels(args, cache, &in_prof, pixels, w, h, nw, nh)?;
+        (w, h) = (nw, nh);
+    }
+
+    // A resized image cannot reproduce the input pixels.
+    let input_md5 = (eff.verify && target.is_none()).then(|| pixels.md5());
+    let input_kind = std::mem::discriminant(&pixels);
+
+    // Set when the output profile equals the input profile and pixels are left untouched.
+    let mut passthrough = false;
 
     // If no ICC transform requested or policy disables ICC output: just depth conversion.
-    if args.no_icc || out_prof_opt.is_none() {
-        match eff.out_depth {
-            BitDepth::B16 => {
-                if out_is_tiff {
-                    write_tiff_rgb16(output, w, h, &rgb16, None, tiff_meta.as_ref())
-                        .with_context(|| format!("Write TIFF RGB16 to {}", output.display()))?;
-                } else {
-                    let mut raw = Vec::<u16>::with_capacity(rgb16.len() * 3);
-                    for p in &rgb16 {
-                        raw.push(p.r);
-                        raw.push(p.g);
-                        raw.push(p.b);
-                    }
-                    let buf = image::ImageBuffer::<image::Rgb<u16>, Vec<u16>>::from_raw(w, h, raw)
-                        .context("Create RGB16 buffer")?;
-                    buf.save(output)
-                        .with_context(|| format!("Save image to {}", output.display()))?;
-                }
+    let out_prof_opt = out_prof_opt.filter(|_| !args.no_icc);
+    let embed_icc_bytes = match &out_prof_opt {
+        None => None,
+        Some(out_prof) => {
+            // Checked before the passthrough: a gray profile must not be embedded in an RGB file
+            if out_prof.color_space() == ColorSpaceSignature::GrayData {
+                bail!("Gray output profiles are not supported (output is always RGB); gray profiles can be used as input profiles");
             }
-            BitDepth::B8 => {
-                let rgb8 = quantize_rgb16_to_rgb8_stream_dither(&rgb16, w, h, eff.tone_map, eff.dither);
-                if out_is_tiff {
-                    write_tiff_rgb8(output, w, h, &rgb8, None, tiff_meta.as_ref())
-                        .with_context(|| format!("Write TIFF RGB8 to {}", output.display()))?;
-                } else {
-                    let mut raw = Vec::<u8>::with_capacity(rgb8.len() * 3);
-                    for p in &rgb8 {
-                        raw.push(p.r);
-                        raw.push(p.g);
-                        raw.push(p.b);
+
+            passthrough = same_profile(&in_prof, out_prof)
+                .context("Compare input and output ICC profiles")?;
+
+            let intent: Intent = eff.intent.into();
+
+            let mut flags = args.precision.flags(args.grid_points);
+            if eff.bpc {
+                flags = flags | Flags::BLACKPOINT_COMPENSATION;
+            }
+
+            if args.precision_check && !passthrough {
+                let (mean, max) = round_trip_error(&in_prof, out_prof, intent, flags)
+                    .context("Measure transform round-trip error")?;
+                eprintln!(
+                    "[precision] {} -> {:?}: round-trip dE00 mean {mean:.4}, max {max:.4}",
+                    input.display(),
+                    args.precision
+                );
+            }
+
+            // Device link shared with other files using the same profiles
+            match &mut pixels {
+                _ if passthrough => {
+                    // Identical profiles: a round trip through the PCS could only add rounding.
+                    if args.debug_icc {
+                        eprintln!(
+                            "[icc] {} -> output profile identical to input, transform skipped",
+                            output.display()
+                        );
                     }
-                    let buf = image::RgbImage::from_raw(w, h, raw).context("Create RGB8 buffer")?;
-                    buf.save(output)
-                        .with_context(|| format!("Save image to {}", output.display()))?;
+                }
+                Pixels::Rgb8(pix) => {
+                    let xform = cache.rgb8(&in_prof, out_prof, intent, flags)?;
+                    transform_in_place_parallel(&xform, pix);
+                }
+                Pixels::Rgb16(pix) => {
+                    let xform = cache.rgb16(&in_prof, out_prof, intent, flags)?;
+                    transform_in_place_parallel(&xform, pix);
+                }
+                Pixels::Rgb32F(pix) => {
+                    // Float transforms are unbounded for matrix/TRC profiles
+                    let xform = cache.rgb_f32(&in_prof, out_prof, intent, flags)?;
+                    transform_in_place_parallel(&xform, pix);
                 }
             }
+
+            // Decide ICC embedding bytes for TIFF and JP2 outputs (MC and UC-II end up here).
+            if out_is_tiff || out_is_jp2 {
+                out_icc_bytes.take()
+            } else {
+                None
+            }
+        }
+    };
+
+    if let Some((nw, nh)) = target.filter(|_| args.resize_space == ResizeSpace::Output) {
+        // Without a transform the pixels are still in the input profile.
+        let working = out_prof_opt.as_ref().unwrap_or(&in_prof);
+        pixels = resize_pixels(args, cache, working, pixels, w, h, nw, nh)?;
+        (w, h) = (nw, nh);
+    }
+
+    let written = match (pixels, eff.out_depth) {
+        (pixels, BitDepth::B32f) => Pixels::Rgb32F(pixels.into_rgb32f()),
+        (pixels, BitDepth::B16) => Pixels::Rgb16(pixels.into_rgb16()),
+        (Pixels::Rgb8(rgb8), BitDepth::B8) => Pixels::Rgb8(rgb8),
+        (pixels, BitDepth::B8) => Pixels::Rgb8(quantize_rgb16_to_rgb8_stream_dither(
+            &pixels.into_rgb16(),
+            w,
+            h,
+            eff.tone_map,
+            eff.dither,
+        )),
+    };
+
+    // A passthrough at unchanged depth must reproduce the input exactly;
+    // otherwise check that the file holds what we wrote.
+    let check = eff.verify.then(|| PixelCheck {
+        expected: match input_md5 {
+            Some(md5) if passthrough && input_kind == std::mem::discriminant(&written) => md5,
+            _ => written.md5(),
+        },
+        keep_8bit: matches!(written, Pixels::Rgb8(_)),
+        want_float: matches!(written, Pixels::Rgb32F(_)),
+    });
+
+    Ok(Some(ConvertedPage {
+        w,
+        h,
+        pixels: written,
+        icc: embed_icc_bytes,
+        meta: out_meta,
+        check,
+    }))
+}
+
+/// (keep_8bit, want_float) for decoding the input.
+fn load_depth(args: &Args, eff: &Effective) -> (bool, bool) {
+    // 8-bit sources going to 8-bit output are transformed natively (RGB_8 -> RGB_8),
+    // unless tone mapping/dithering (which work on 16-bit data) or --force-16bit ask
+    // for the 16-bit path.
+    let keep_8bit = !args.force_16bit
+        && matches!(eff.out_depth, BitDepth::B8)
+        && matches!(eff.tone_map, ToneMap::None)
+        && eff.dither.is_none();
+
+    // Float output, or a float source, runs the whole pipeline in float.
+    let want_float = matches!(eff.out_depth, BitDepth::B32f);
+    (keep_8bit, want_float)
+}
+
+/// Resample `pixels` to `nw`×`nh` in the linear-light version of `prof`;
+/// the result is float in `prof`.
+#[allow(clippy::too_many_arguments)]
+fn resize_pixels(
+    args: &Args,
+    cache: &TransformCache,
+    prof: &Profile,
+    pixels: Pixels,
+    w: u32,
+    h: u32,
+    nw: u32,
+    nh: u32,
+) -> Result<Pixels> {
+    let light = LinearLight::new(args, cache, prof)?;
+    let linear = light.linearize(pixels.into_rgb32f());
+    let pix = resize::resample(&linear, w, h, nw, nh, args.resize_filter);
+    Ok(Pixels::Rgb32F(light.delinearize(&pix)))
+}
+
+/// Transforms between a working space and its linear-light version, for resampling.
+struct LinearLight {
+    to_linear: Arc<SharedTransform<RGB<f32>>>,
+    from_linear: Arc<SharedTransform<RGB<f32>>>,
+    gray: bool,
+}
+
+impl LinearLight {
+    fn new(args: &Args, cache: &TransformCache, prof: &Profile) -> Result<Self> {
+        let linear = icc::linear_rgb(prof).context("Build linear-light working space for --resize")?;
+        let intent = Intent::RelativeColorimetric;
+        let flags = args.precision.flags(args.grid_points);
+        Ok(Self {
+            to_linear: cache.rgb_f32(prof, &linear, intent, flags)?,
+            from_linear: cache.rgb_f32(&linear, prof, intent, flags)?,
+            gray: prof.color_space() == ColorSpaceSignature::GrayData,
+        })
+    }
+
+    /// Working-space samples (0..1) to linear light, in place.
+    fn linearize(&self, mut pix: Vec<RGB<f32>>) -> Vec<RGB<f32>> {
+        transform_in_place_parallel(&self.to_linear, &mut pix);
+        pix
+    }
+
+    /// Linear light back to the working space.
+    fn delinearize(&self, linear: &[RGB<f32>]) -> Vec<RGB<f32>> {
+        let mut pix = linear.to_vec();
+        transform_in_place_parallel(&self.from_linear, &mut pix);
+        if self.gray {
+            // The gray transform only wrote the first sample
+            pix.par_iter_mut().for_each(|p| {
+                p.g = p.r;
+                p.b = p.r;
+            });
         }
+        pix
+    }
+}
+
+/// Input resolution in dots per inch (x, y), if stated.
+fn resolution_dpi(meta: Option<&TiffMeta>) -> Option<(f64, f64)> {
+    resolution_ppm(meta).map(|(x, y)| (x * 0.0254, y * 0.0254))
+}
+
+/// Resolution written after resizing `w`×`h` to `nw`×`nh`: the --resize dpi
+/// target, or the input resolution scaled with the pixel count.
+fn resize_resolution(meta: &mut TiffMeta, spec: Option<ResizeSpec>, w: u32, h: u32, nw: u32, nh: u32) {
+    let Some((x_dpi, y_dpi)) = resolution_dpi(Some(meta)) else {
+        return;
+    };
+    let (x_dpi, y_dpi) = match spec {
+        Some(ResizeSpec::Dpi(dpi)) => (dpi, dpi),
+        _ => (x_dpi * nw as f64 / w as f64, y_dpi * nh as f64 / h as f64),
+    };
+    meta.x_res = Some(rational_from_f64(x_dpi));
+    meta.y_res = Some(rational_from_f64(y_dpi));
+    meta.unit = Some(ResolutionUnit::Inch);
+}
+
+/// Re-decode `output` and compare its pixel MD5 with `expected`.
+fn verify_output_pixels(
+    eff: &Effective,
+    output: &Path,
+    ifd: usize,
+    check: PixelCheck,
+) -> Result<()> {
+    let PixelCheck { expected, keep_8bit, want_float } = check;
+    let lossy = match file_ext_lower(output).as_str() {
+        "jpg" | "jpeg" => true,
+        "jp2" | "jpx" => jp2_params(eff.preset) == Jp2Params::Lossy,
+        _ => false,
+    };
+    if lossy {
+        eprintln!(
+            "Warning: pixel verification skipped for lossy output {}",
+            output.display()
+        );
         return Ok(());
     }
 
-    let out_prof = out_prof_opt.expect("checked above");
-    let intent: Intent = eff.intent.into();
+    let decoded = load_image(output, ifd, keep_8bit, want_float)
+        .with_context(|| format!("Re-read output for verification: {}", output.display()))?;
+    let actual = decoded.pixels.md5();
+    if actual != expected {
+        bail!(
+            "Pixel verification failed for {}{}: expected MD5 {}, output decodes to {}",
+            output.display(),
+            if ifd > 0 { format!(" (page {})", ifd + 1) } else { String::new() },
+            hex(&expected),
+            hex(&actual)
+        );
+    }
+    Ok(())
+}
 
-    let mut flags = Flags::default();
-    if eff.bpc {
-        flags = flags | Flags::BLACKPOINT_COMPENSATION;
+/// Write RGB32F pixels (TIFF only; checked before decoding in `convert_one_in_pool`).
+fn save_rgb32f(
+    output: &Path,
+    w: u32,
+    h: u32,
+    pix: &[RGB<f32>],
+    icc: Option<&[u8]>,
+    meta: Option<&TiffMeta>,
+) -> Result<()> {
+    write_tiff_rgb32f(&mut create_tiff(output)?, w, h, pix, icc, meta)
+        .with_context(|| format!("Write TIFF RGB32F to {}", output.display()))
+}
+
+/// Write RGB16 pixels: TIFF via our writer (ICC + resolution), other formats via [`save_image`].
+fn save_rgb16(
+    output: &Path,
+    w: u32,
+    h: u32,
+    rgb16: &[RGB16],
+    icc: Option<&[u8]>,
+    meta: Option<&TiffMeta>,
+) -> Result<()> {
+    if is_tiff_path(output) {
+        return write_tiff_rgb16(&mut create_tiff(output)?, w, h, rgb16, icc, meta)
+            .with_context(|| format!("Write TIFF RGB16 to {}", output.display()));
     }
 
-    // Transform in 16-bit
-    let xform: Transform<RGB16, RGB16> = Transform::new_flags(
-        &in_prof,
-        PixelFormat::RGB_16,
-        &out_prof,
-        PixelFormat::RGB_16,
-        intent,
-        flags,
-    )?;
-    xform.transform_in_place(&mut rgb16);
+    let mut raw = Vec::<u16>::with_capacity(rgb16.len() * 3);
+    for p in rgb16 {
+        raw.push(p.r);
+        raw.push(p.g);
+        raw.push(p.b);
+    }
+    let buf = image::ImageBuffer::<image::Rgb<u16>, Vec<u16>>::from_raw(w, h, raw)
+        .context("Create RGB16 buffer")?;
+    save_image(output, &image::DynamicImage::ImageRgb16(buf), meta)
+        .with_context(|| format!("Save image to {}", output.display()))
+}
 
-    // Decide ICC embedding bytes for TIFF outputs (MC and UC-II end up here).
-    let embed_icc_bytes = if out_is_tiff {
-        match out_prof.icc() {
-            Ok(bytes) => Some(bytes),
-            Err(e) => {
-                eprintln!(
-                    "Warning: could not export output ICC bytes for {}: {}",
-                    output.display(),
-                    e
-                );
-                None
+/// Write RGB8 pixels: TIFF via our writer (ICC + resolution), other formats via [`save_image`].
+fn save_rgb8(
+    output: &Path,
+    w: u32,
+    h: u32,
+    rgb8: &[RGB8],
+    icc: Option<&[u8]>,
+    meta: Option<&TiffMeta>,
+) -> Result<()> {
+    if is_tiff_path(output) {
+        return write_tiff_rgb8(&mut create_tiff(output)?, w, h, rgb8, icc, meta)
+            .with_context(|| format!("Write TIFF RGB8 to {}", output.display()));
+    }
+
+    let mut raw = Vec::<u8>::with_capacity(rgb8.len() * 3);
+    for p in rgb8 {
+        raw.push(p.r);
+        raw.push(p.g);
+        raw.push(p.b);
+    }
+    let buf = image::RgbImage::from_raw(w, h, raw).context("Create RGB8 buffer")?;
+    save_image(output, &image::DynamicImage::ImageRgb8(buf), meta)
+        .with_context(|| format!("Save image to {}", output.display()))
+}
+
+/// Write a non-TIFF image via `image`; PNG gets `pHYs` and JPEG the JFIF
+/// density when the resolution is known, and both an EXIF Orientation
+/// under --orientation preserve.
+fn save_image(output: &Path, img: &image::DynamicImage, meta: Option<&TiffMeta>) -> Result<()> {
+    let res = normalize_resolution(meta);
+    let exif = meta.and_then(|m| m.orientation).map(orientation_exif);
+    match file_ext_lower(output).as_str() {
+        "png" => save_png(output, img, resolution_ppm(meta), exif),
+        "jpg" | "jpeg" => {
+            use image::codecs::jpeg::{JpegEncoder, PixelDensity, PixelDensityUnit};
+            let f = File::create(output)?;
+            let mut enc = JpegEncoder::new(BufWriter::new(f));
+            if let Some((unit, xr, yr)) = res {
+                // JFIF stores whole dots per inch/cm
+                let density = |r: &Rational| (r.n as f64 / r.d as f64).round();
+                let (x, y) = (density(&xr), density(&yr));
+                if x >= 1.0 && y >= 1.0 && x <= u16::MAX as f64 && y <= u16::MAX as f64 {
+                    enc.set_pixel_density(PixelDensity {
+                        density: (x as u16, y as u16),
+                        unit: match unit {
+                            ResolutionUnit::Centimeter => PixelDensityUnit::Centimeters,
+                            _ => PixelDensityUnit::Inches,
+                        },
+                    });
+                }
             }
+            if let Some(exif) = exif {
+                image::ImageEncoder::set_exif_metadata(&mut enc, exif)?;
+            }
+            Ok(enc.encode_image(img)?)
         }
-    } else {
-        None
+        _ => Ok(img.save(output)?),
+    }
+}
+
+/// Write RGB8/RGB16 PNG with the png crate (`image` cannot write `pHYs`).
+fn save_png(
+    output: &Path,
+    img: &image::DynamicImage,
+    resolution_ppm: Option<(f64, f64)>,
+    exif: Option<Vec<u8>>,
+) -> Result<()> {
+    let (depth, data): (png::BitDepth, Cow<[u8]>) = match img {
+        image::DynamicImage::ImageRgb8(buf) => (png::BitDepth::Eight, Cow::Borrowed(buf.as_raw())),
+        image::DynamicImage::ImageRgb16(buf) => (
+            png::BitDepth::Sixteen,
+            Cow::Owned(buf.as_raw().iter().flat_map(|v| v.to_be_bytes()).collect()),
+        ),
+        _ => bail!("Unsupported PNG pixel format"),
     };
+    let mut info = png::Info::with_size(img.width(), img.height());
+    info.color_type = png::ColorType::Rgb;
+    info.bit_depth = depth;
+    info.pixel_dims = resolution_ppm.map(|(x, y)| png::PixelDimensions {
+        xppu: x.round() as u32,
+        yppu: y.round() as u32,
+        unit: png::Unit::Meter,
+    });
+    info.exif_metadata = exif.map(Cow::Owned);
+    let f = File::create(output)?;
+    let mut writer = png::Encoder::with_info(BufWriter::new(f), info)?.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(writer.finish()?)
+}
 
-    match eff.out_depth {
-        BitDepth::B16 => {
-            if out_is_tiff {
-                write_tiff_rgb16(
-                    output,
-                    w,
-                    h,
-                    &rgb16,
-                    embed_icc_bytes.as_deref(),
-                    tiff_meta.as_ref(),
-                )
-                .with_context(|| {
-                    format!("Write TIFF RGB16 with ICC to {}", output.display())
-                })?;
-            } else {
-                let mut raw = Vec::<u16>::with_capacity(rgb16.len() * 3);
-                for p in &rgb16 {
-                    raw.push(p.r);
-                    raw.push(p.g);
-                    raw.push(p.b);
-                }
-                let buf = image::ImageBuffer::<image::Rgb<u16>, Vec<u16>>::from_raw(w, h, raw)
-                    .context("Create RGB16 buffer")?;
-                buf.save(output)
-                    .with_context(|| format!("Save image to {}", output.display()))?;
+/// Write JP2 with the NDK parameter set of the preset, the output profile in
+/// `colr` and the capture resolution in `res `.
+#[allow(clippy::too_many_arguments)]
+fn save_jp2(
+    eff: &Effective,
+    output: &Path,
+    w: u32,
+    h: u32,
+    pixels: &Pixels,
+    icc: Option<&[u8]>,
+    meta: Option<&TiffMeta>,
+) -> Result<()> {
+    let params = jp2_params(eff.preset);
+    if let Some(o) = meta.and_then(|m| m.orientation) {
+        eprintln!(
+            "Warning: {}: JP2 has no Orientation, Orientation={o} is dropped (use --orientation apply)",
+            output.display()
+        );
+    }
+
+    let res = resolution_ppm(meta);
+    match pixels {
+        Pixels::Rgb16(pix) => jp2::encode(output, w, h, pix, params, icc, res),
+        Pixels::Rgb8(pix) => jp2::encode(output, w, h, pix, params, icc, res),
+        Pixels::Rgb32F(_) => bail!("JP2 output does not support 32-bit float samples"),
+    }
+    .with_context(|| format!("Write JP2 {}", output.display()))
+}
+
+/// Minimal EXIF (TIFF structure, little endian) holding only an Orientation.
+fn orientation_exif(o: u16) -> Vec<u8> {
+    let mut exif = Vec::with_capacity(26);
+    exif.extend_from_slice(b"II*\0");
+    exif.extend_from_slice(&8u32.to_le_bytes()); // IFD0 offset
+    exif.extend_from_slice(&1u16.to_le_bytes()); // one entry
+    exif.extend_from_slice(&274u16.to_le_bytes()); // Orientation
+    exif.extend_from_slice(&3u16.to_le_bytes()); // SHORT
+    exif.extend_from_slice(&1u32.to_le_bytes()); // count
+    exif.extend_from_slice(&o.to_le_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    exif.extend_from_slice(&0u32.to_le_bytes()); // no next IFD
+    exif
+}
+
+/// NDK JPEG 2000 parameter set of a preset.
+fn jp2_params(preset: Preset) -> Jp2Params {
+    match preset {
+        Preset::NdkMc => Jp2Params::Lossless,
+        Preset::NdkUcI | Preset::NdkUcII => Jp2Params::Lossy,
+        // Archival masters
+        _ => Jp2Params::Lossless,
+    }
+}
+
+/// --missing-resolution for page `n` (0-based) of `input`, which states no resolution.
+fn apply_missing_resolution(
+    args: &Args,
+    eff: &Effective,
+    input: &Path,
+    n: usize,
+    meta: &mut Option<TiffMeta>,
+) -> Result<()> {
+    match eff.missing_resolution {
+        MissingResolution::Error => bail!(
+            "{} (page {}): no resolution in the input (--missing-resolution error)",
+            input.display(),
+            n + 1
+        ),
+        MissingResolution::Omit => {
+            if args.debug_icc {
+                eprintln!("[res] {} (page {}): no resolution, none written", input.display(), n + 1);
             }
         }
-        BitDepth::B8 => {
-            let rgb8 = quantize_rgb16_to_rgb8_stream_dither(&rgb16, w, h, eff.tone_map, eff.dither);
-            if out_is_tiff {
-                write_tiff_rgb8(
-                    output,
-                    w,
-                    h,
-                    &rgb8,
-                    embed_icc_bytes.as_deref(),
-                    tiff_meta.as_ref(),
-                )
-                .with_context(|| format!("Write TIFF RGB8 with ICC to {}", output.display()))?;
-            } else {
-                let mut raw = Vec::<u8>::with_capacity(rgb8.len() * 3);
-                for p in &rgb8 {
-                    raw.push(p.r);
-                    raw.push(p.g);
-                    raw.push(p.b);
-                }
-                let buf = image::RgbImage::from_raw(w, h, raw).context("Create RGB8 buffer")?;
-                buf.save(output)
-                    .with_context(|| format!("Save image to {}", output.display()))?;
+        MissingResolution::Assume(dpi) => {
+            let dpi = rational_from_f64(dpi);
+            let m = meta.get_or_insert_with(TiffMeta::default);
+            m.x_res = Some(rat(&dpi));
+            m.y_res = Some(dpi);
+            m.unit = Some(ResolutionUnit::Inch);
+        }
+    }
+    Ok(())
+}
+
+/// `v` as a whole number when it is one, else in thousandths.
+fn rational_from_f64(v: f64) -> Rational {
+    if (v - v.round()).abs() < 1e-6 {
+        Rational { n: v.round() as u32, d: 1 }
+    } else {
+        Rational { n: (v * 1000.0).round() as u32, d: 1000 }
+    }
+}
+
+/// --orientation: move the Orientation to `reorient` (apply; a 90° turn also
+/// swaps the resolution axes), keep it for the output (preserve) or drop it.
+fn apply_orientation_policy(eff: &Effective, meta: &mut TiffMeta) {
+    match eff.orientation {
+        Orientation::Apply => {
+            meta.reorient = meta.orientation.take();
+            if meta.reorient.is_some_and(|o| o >= 5) {
+                std::mem::swap(&mut meta.x_res, &mut meta.y_res);
             }
         }
+        Orientation::Preserve => {}
+        Orientation::Ignore => meta.orientation = None,
     }
+}
 
-    Ok(())
+/// Output resolution in pixels per metre (x, y).
+fn resolution_ppm(meta: Option<&TiffMeta>) -> Option<(f64, f64)> {
+    let (unit, xr, yr) = normalize_resolution(meta)?;
+    let per_metre = match unit {
+        ResolutionUnit::Centimeter => 100.0,
+        _ => 100.0 / 2.54,
+    };
+    let ppm = |r: &Rational| r.n as f64 / r.d as f64 * per_metre;
+    Some((ppm(&xr), ppm(&yr)))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_batch_conversion(
     args: &Args,
     eff: &Effective,
+    catalog: &IccCatalog,
     in_dir: &Path,
     out_dir: &Path,
     out_ext: &str,
     inputs: Vec<PathBuf>,
     jobs: Option<usize>,
 ) -> Result<()> {
+    // Transforms are shared by all workers; a batch usually needs only one.
+    let cache = TransformCache::new();
+
     // Funkce pro zpracování jednoho souboru v batch režimu
     let process_single = |input_path: &Path| -> Result<()> {
         let rel = match input_path.strip_prefix(in_dir) {
@@ -1296,15 +3166,20 @@ fn process_batch_conversion(
             ));
         }
 
-        let out_name = format!("{}{}.{}", stem_str, args.suffix, out_ext);
+        // A tile pyramid is a directory named like the output file, without extension
+        let out_name = match args.tiles {
+            Some(_) => format!("{}{}", stem_str, args.suffix),
+            None => format!("{}{}.{}", stem_str, args.suffix, out_ext),
+        };
         let out_path = target_dir.join(out_name);
 
-        if out_path.exists() && !args.overwrite {
+        // Derivatives check their own output paths
+        if args.derivative.is_empty() && output_exists(args, &out_path) && !args.overwrite {
             eprintln!("Skipping existing: {}", out_path.display());
             return Ok(());
         }
 
-        convert_one(args, eff, input_path, &out_path)
+        convert_one(args, eff, &cache, catalog, input_path, &out_path)
             .map_err(|e| anyhow!("{} -> {}: {}", input_path.display(), out_path.display(), e))
     };
 
@@ -1335,6 +3210,9 @@ fn process_batch_conversion(
                 }
             }
 
+            let (built, reused) = cache.stats();
+            eprintln!("ICC transforms: {built} built, {reused} reused from cache.");
+
             if !errs.is_empty() {
                 eprintln!("Completed with errors: ok={ok}, errors={}", errs.len());
                 for e in errs.iter().take(20) {
@@ -1354,13 +3232,136 @@ fn process_batch_conversion(
     run()
 }
 
+/// --check: report which of the INPUT files meet the requirements of `preset`.
+/// The preset may come from the config file's default, so it is checked here
+/// rather than by clap.
+fn check_compliance(args: &Args, preset: Preset) -> Result<()> {
+    let Some(req) = compliance::requirements(preset) else {
+        bail!("--check needs an archival preset (--preset or the config default: fadgi-*, metamorfoze, iso19264-*)");
+    };
+    let files = if args.input.is_dir() {
+        collect_input_files(&args.input, args.recursive)?
+    } else {
+        vec![args.input.clone()]
+    };
+
+    let results: Vec<(&PathBuf, Result<Vec<String>>)> = files
+        .par_iter()
+        .map(|f| (f, compliance::check_file(f, req, args.chromatic_adaptation)))
+        .collect();
+    let mut failed = 0;
+    for (file, result) in &results {
+        match result {
+            Ok(issues) if issues.is_empty() => println!("PASS {}", file.display()),
+            Ok(issues) => {
+                failed += 1;
+                println!("FAIL {}: {}", file.display(), issues.join("; "));
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}: {e:#}", file.display());
+            }
+        }
+    }
+
+    let name = args.preset.as_deref().unwrap_or_default();
+    eprintln!("{name}: {} of {} files compliant.", results.len() - failed, results.len());
+    if failed > 0 {
+        bail!("{failed} files do not meet {name}");
+    }
+    Ok(())
+}
+
+/// Parse the command line, expanding config presets (global and per --derivative).
+fn parse_args() -> Result<Args> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let args = Args::parse_from(&argv);
+    let config = Config::load(args.config.as_deref())?;
+
+    let name = args.preset.clone().or_else(|| config.default_preset.clone());
+    let mut args = args_with_preset(&config, name.as_deref(), &argv)?;
+    for d in &mut args.derivative {
+        if let Some(name) = &d.preset {
+            d.base = Some(Box::new(args_with_preset(&config, Some(name), &argv)?));
+        }
+    }
+    Ok(args)
+}
+
+/// The command line `argv` with preset `name` applied: a built-in preset, a
+/// config preset (its options go before the user's), or a config override
+/// of a built-in one.
+fn args_with_preset(config: &Config, name: Option<&str>, argv: &[std::ffi::OsString]) -> Result<Args> {
+    let Some(name) = name else {
+        return Ok(Args::parse_from(argv));
+    };
+    let def = config.preset(name);
+    let builtin = Preset::from_str(name, false).ok();
+    let policy = match (builtin, def.and_then(|d| d.policy.as_deref())) {
+        (Some(_), Some(_)) => bail!(
+            "{}: presets.{name}.policy cannot be set for a built-in preset",
+            def.map_or(Path::new(""), |d| &d.source).display()
+        ),
+        (Some(builtin), None) => builtin,
+        (None, Some(policy)) => Preset::from_str(policy, false).map_err(|_| {
+            anyhow!(
+                "{}: presets.{name}.policy: unknown policy '{policy}' (a built-in preset name)",
+                def.map_or(Path::new(""), |d| &d.source).display()
+            )
+        })?,
+        (None, None) if def.is_some() => Preset::NdkUcII,
+        (None, None) => {
+            let builtins: Vec<String> = Preset::value_variants()
+                .iter()
+                .filter_map(|p| p.to_possible_value().map(|v| v.get_name().to_string()))
+                .collect();
+            let custom = config.preset_names().filter(|n| !builtins.iter().any(|b| b == n));
+            let known: Vec<&str> = builtins.iter().map(String::as_str).chain(custom).collect();
+            bail!("Unknown preset '{name}' (known: {})", known.join(", "));
+        }
+    };
+
+    let mut args = match def {
+        None => Args::parse_from(argv),
+        Some(def) => {
+            let expanded = def.cli_args(&Args::command())?;
+            let argv = argv[..1].iter().chain(&expanded).chain(&argv[1..]);
+            Args::try_parse_from(argv).with_context(|| format!("Preset '{name}' from {}", def.source.display()))?
+        }
+    };
+    args.preset = Some(name.to_string());
+    args.policy = Some(policy);
+    Ok(args)
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = parse_args()?;
     let eff = compute_effective(&args);
 
+    // Transforms are built on the global lcms2 context, which holds the adaptation state.
+    unsafe { lcms2_sys::cmsSetAdaptationState(args.adaptation_state) };
+    if matches!(eff.intent, RenderIntent::Absolute) {
+        eprintln!(
+            "Absolute colorimetric intent: adaptation state {}, chromatic adaptation {}",
+            args.adaptation_state,
+            args.chromatic_adaptation.name()
+        );
+    }
+
+    if args.check {
+        return check_compliance(&args, eff.preset);
+    }
+    let output = args.output.clone().context("--output is required")?;
+
+    let catalog = IccCatalog::load(args.icc_dir.as_deref(), args.icc_map.as_deref())?;
+    if args.icc_dir.is_some() || args.icc_map.is_some() {
+        let (profiles, rules) = catalog.stats();
+        eprintln!("ICC catalogue: {profiles} profiles indexed, {rules} substitution rules");
+    }
+
     if args.input.is_dir() {
         let in_dir = &args.input;
-        let out_dir = &args.output;
+        let out_dir = &output;
 
         if out_dir.exists() && !out_dir.is_dir() {
             bail!(
@@ -1388,31 +3389,252 @@ fn main() -> Result<()> {
 
         let jobs = if args.jobs == 0 { None } else { Some(args.jobs) };
 
-        process_batch_conversion(&args, &eff, in_dir, out_dir, &out_ext, inputs, jobs)?;
+        process_batch_conversion(&args, &eff, &catalog, in_dir, out_dir, &out_ext, inputs, jobs)?;
     } else {
         // Single-file mode
-        if args.output.is_dir() {
+        if output.is_dir() && args.tiles.is_none() {
             bail!(
                 "OUTPUT must be a file when INPUT is a file: {}",
-                args.output.display()
+                output.display()
             );
         }
 
-        if args.output.exists() && !args.overwrite {
+        if args.derivative.is_empty() && output_exists(&args, &output) && !args.overwrite {
             bail!(
                 "Output file already exists: {}. Use --overwrite to replace.",
-                args.output.display()
+                output.display()
             );
         }
 
-        convert_one(&args, &eff, &args.input, &args.output).with_context(|| {
+        convert_one(&args, &eff, &TransformCache::new(), &catalog, &args.input, &output).with_context(|| {
             format!(
                 "Failed to convert {} to {}",
                 args.input.display(),
-                args.output.display()
+                output.display()
             )
         })?;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3×2 image, row by row:
+    ///
+    /// ```text
+    /// 0 1 2
+    /// 3 4 5
+    /// ```
+    const SRC: [u8; 6] = [0, 1, 2, 3, 4, 5];
+
+    #[test]
+    fn reorient_3x2() {
+        let cases: [(u16, [u8; 6]); 7] = [
+            // Mirrored horizontally
+            (2, [2, 1, 0, 5, 4, 3]),
+            // Rotated 180°
+            (3, [5, 4, 3, 2, 1, 0]),
+            // Mirrored vertically
+            (4, [3, 4, 5, 0, 1, 2]),
+            // Transposed (2×3 from here on)
+            (5, [0, 3, 1, 4, 2, 5]),
+            // Rotated 90° clockwise
+            (6, [3, 0, 4, 1, 5, 2]),
+            // Transversed
+            (7, [5, 2, 4, 1, 3, 0]),
+            // Rotated 90° counter-clockwise
+            (8, [2, 5, 1, 4, 0, 3]),
+        ];
+        for (o, expected) in cases {
+            assert_eq!(reorient(3, 2, &SRC, o), expected, "Orientation {o}");
+        }
+    }
+
+    #[test]
+    fn reoriented_swaps_size() {
+        let pixels = Pixels::Rgb8(SRC.iter().map(|&v| RGB8::new(v, v, v)).collect());
+        let (w, h, _) = pixels.reoriented(3, 2, 6);
+        assert_eq!((w, h), (2, 3));
+        let pixels = Pixels::Rgb8(SRC.iter().map(|&v| RGB8::new(v, v, v)).collect());
+        let (w, h, _) = pixels.reoriented(3, 2, 3);
+        assert_eq!((w, h), (3, 2));
+    }
+
+    /// Write `bytes` to a file named `name` in the temp dir.
+    fn fixture(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("nclr-test-{}-{name}", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// An IFD entry: tag, type, count and value bytes.
+    type Entry = (u16, u16, u32, Vec<u8>);
+
+    fn short(tag: u16, v: u16) -> Entry {
+        (tag, 3, 1, v.to_le_bytes().to_vec())
+    }
+
+    fn long(tag: u16, v: u32) -> Entry {
+        (tag, 4, 1, v.to_le_bytes().to_vec())
+    }
+
+    fn rational(tag: u16, n: u32, d: u32) -> Entry {
+        (tag, 5, 1, [n.to_le_bytes(), d.to_le_bytes()].concat())
+    }
+
+    /// Little-endian classic TIFF with the IFDs chained in order; the last one
+    /// links back to the first when `looped` is set.
+    fn tiff(ifds: &[Vec<Entry>], looped: bool) -> Vec<u8> {
+        let mut out = b"II*\0\x08\0\0\0".to_vec();
+        for (i, entries) in ifds.iter().enumerate() {
+            let data_off = out.len() as u32 + 2 + 12 * entries.len() as u32 + 4;
+            let mut data = Vec::<u8>::new();
+            out.extend((entries.len() as u16).to_le_bytes());
+            for (tag, ty, count, value) in entries {
+                out.extend(tag.to_le_bytes());
+                out.extend(ty.to_le_bytes());
+                out.extend(count.to_le_bytes());
+                if value.len() <= 4 {
+                    let mut field = [0u8; 4];
+                    field[..value.len()].copy_from_slice(value);
+                    out.extend(field);
+                } else {
+                    out.extend((data_off + data.len() as u32).to_le_bytes());
+                    data.extend(value);
+                }
+            }
+            let next = if i + 1 < ifds.len() {
+                data_off + data.len() as u32
+            } else if looped {
+                8
+            } else {
+                0
+            };
+            out.extend(next.to_le_bytes());
+            out.extend(data);
+        }
+        out
+    }
+
+    #[test]
+    fn tiff_pages_skip_reduced_subfiles() {
+        let path = fixture(
+            "pages.tif",
+            &tiff(
+                &[
+                    vec![rational(282, 300, 1), rational(283, 300, 1), short(296, 2)],
+                    // Thumbnail (NewSubfileType bit 0)
+                    vec![long(254, 1)],
+                    vec![long(254, 0), short(274, 6)],
+                ],
+                false,
+            ),
+        );
+        let pages = read_tiff_pages(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(pages.iter().map(|m| m.ifd).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!(pages[0].x_res.as_ref().map(|r| (r.n, r.d)), Some((300, 1)));
+        assert!(matches!(pages[0].unit, Some(ResolutionUnit::Inch)));
+        assert_eq!(pages[1].orientation, Some(6));
+    }
+
+    #[test]
+    fn tiff_ifd_loop_is_an_error() {
+        let path = fixture("loop.tif", &tiff(&[vec![short(274, 1)], vec![short(274, 1)]], true));
+        let err = read_tiff_pages(&path).err().map(|e| e.to_string());
+        fs::remove_file(&path).ok();
+        assert_eq!(err.as_deref(), Some("TIFF IFD chain loops at offset 8"));
+    }
+
+    #[test]
+    fn dpi_rational_snaps_whole_dpi() {
+        let dpi = |ppm| {
+            let r = dpi_rational(ppm);
+            (r.n, r.d)
+        };
+        assert_eq!(dpi(11811.0), (300, 1));
+        assert_eq!(dpi(2835.0), (72, 1));
+        // 1000 ppm is 25.4 dpi, not a whole dpi
+        assert_eq!(dpi(1000.0), (25400, 1000));
+    }
+
+    /// 1×1 gray PNG with a `pHYs` chunk.
+    fn png_with_phys(xppu: u32, yppu: u32, unit: png::Unit) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut enc = png::Encoder::new(&mut out, 1, 1);
+        enc.set_pixel_dims(Some(png::PixelDimensions { xppu, yppu, unit }));
+        enc.write_header().unwrap().write_image_data(&[0]).unwrap();
+        out
+    }
+
+    #[test]
+    fn png_phys_resolution() {
+        let path = fixture("metre.png", &png_with_phys(11811, 5906, png::Unit::Meter));
+        let meta = read_png_resolution(&path).unwrap().unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(meta.x_res.map(|r| (r.n, r.d)), Some((300, 1)));
+        assert_eq!(meta.y_res.map(|r| (r.n, r.d)), Some((150, 1)));
+        assert!(matches!(meta.unit, Some(ResolutionUnit::Inch)));
+
+        // Unit 0 is an aspect ratio only
+        let path = fixture("aspect.png", &png_with_phys(1, 1, png::Unit::Unspecified));
+        let meta = read_png_resolution(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert!(meta.is_none());
+    }
+
+    /// JPEG marker segment `marker` with `payload`.
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() + 2) as u16;
+        [&[0xFF, marker][..], &len.to_be_bytes(), payload].concat()
+    }
+
+    /// JFIF APP0 payload with density `x`×`y` in `unit`.
+    fn jfif(unit: u8, x: u16, y: u16) -> Vec<u8> {
+        [&b"JFIF\0\x01\x02"[..], &[unit], &x.to_be_bytes(), &y.to_be_bytes(), &[0, 0]].concat()
+    }
+
+    /// SOI, the segments, then EOI (no image data).
+    fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        [&[0xFF, 0xD8][..], &segments.concat(), &[0xFF, 0xD9]].concat()
+    }
+
+    #[test]
+    fn jpeg_segments_stop_at_eoi() {
+        let data = jpeg(&[segment(0xE0, &jfif(1, 72, 72)), segment(0xE2, b"ICC")]);
+        let segments = jpeg_segments(&data);
+        assert_eq!(segments.iter().map(|&(m, _)| m).collect::<Vec<_>>(), [0xE0, 0xE2]);
+        assert_eq!(segments[1].1, b"ICC");
+        assert!(jpeg_segments(b"not a jpeg").is_empty());
+    }
+
+    #[test]
+    fn jfif_density_in_dots_per_cm() {
+        let path = fixture("cm.jpg", &jpeg(&[segment(0xE0, &jfif(2, 118, 59))]));
+        let meta = read_jfif_resolution(&path).unwrap().unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(meta.x_res.map(|r| (r.n, r.d)), Some((118, 1)));
+        assert_eq!(meta.y_res.map(|r| (r.n, r.d)), Some((59, 1)));
+        assert!(matches!(meta.unit, Some(ResolutionUnit::Centimeter)));
+    }
+
+    #[test]
+    fn exif_resolution_when_jfif_has_no_unit() {
+        let ifd0 = vec![rational(282, 150, 1), rational(283, 150, 1), short(296, 3), short(274, 8)];
+        let exif = tiff(&[ifd0], false);
+        let data = jpeg(&[
+            segment(0xE0, &jfif(0, 1, 1)),
+            segment(0xE1, &[&b"Exif\0\0"[..], &exif].concat()),
+        ]);
+        let path = fixture("exif.jpg", &data);
+        let meta = read_raster_meta(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(meta.x_res.map(|r| (r.n, r.d)), Some((150, 1)));
+        assert!(matches!(meta.unit, Some(ResolutionUnit::Centimeter)));
+        assert_eq!(meta.orientation, Some(8));
+    }
+}
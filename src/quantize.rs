@@ -0,0 +1,414 @@
+// ---------------- Quantization + tonemapping + dithering ----------------
+//
+// 16->8 bit conversion after the ICC transform. All dithering methods are
+// deterministic: the same input always yields the same 8-bit output, and work
+// is split over rayon in a way that does not depend on the thread count.
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use rgb::{RGB8, RGB16};
+use std::sync::OnceLock;
+
+use crate::ToneMap;
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum DitherMethod {
+    /// Floyd–Steinberg error diffusion (serpentine scan, full-precision error)
+    FloydSteinberg,
+    /// Jarvis–Judice–Ninke error diffusion (12 neighbours, serpentine)
+    #[value(name = "jjn")]
+    JarvisJudiceNinke,
+    /// Stucki error diffusion (12 neighbours, serpentine)
+    Stucki,
+    /// Sierra (three-row) error diffusion (10 neighbours, serpentine)
+    Sierra,
+    /// Ordered dithering with an 8x8 Bayer matrix
+    Bayer,
+    /// Ordered dithering with a deterministic 64x64 blue-noise mask
+    BlueNoise,
+}
+
+#[inline]
+pub fn apply_tonemap_norm(x: f32, tone: ToneMap) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    match tone {
+        ToneMap::None => x,
+        ToneMap::Gamma => x.powf(1.0 / 2.2),
+        ToneMap::Perceptual => x.sqrt(),
+    }
+}
+
+/// Tone-mapped value of a 16-bit sample on the 0..255 scale (not yet rounded).
+#[inline]
+fn level8(v: u16, tone: ToneMap) -> f32 {
+    apply_tonemap_norm(v as f32 / 65535.0, tone) * 255.0
+}
+
+pub fn quantize_rgb16_to_rgb8_stream_dither(
+    pix: &[RGB16],
+    w: u32,
+    h: u32,
+    tone: ToneMap,
+    dither: Option<DitherMethod>,
+) -> Vec<RGB8> {
+    let w = w as usize;
+    let h = h as usize;
+
+    let mut out = vec![RGB8::new(0, 0, 0); w * h];
+    if out.is_empty() {
+        return out;
+    }
+
+    match dither {
+        None => {
+            out.par_chunks_mut(w)
+                .zip(pix.par_chunks(w))
+                .for_each(|(orow, prow)| {
+                    for (o, p) in orow.iter_mut().zip(prow) {
+                        let r = (level8(p.r, tone) + 0.5) as i32;
+                        let g = (level8(p.g, tone) + 0.5) as i32;
+                        let b = (level8(p.b, tone) + 0.5) as i32;
+                        *o = RGB8::new(
+                            r.clamp(0, 255) as u8,
+                            g.clamp(0, 255) as u8,
+                            b.clamp(0, 255) as u8,
+                        );
+                    }
+                });
+        }
+        Some(DitherMethod::Bayer) => ordered_dither(pix, &mut out, w, tone, &BAYER8, 8),
+        Some(DitherMethod::BlueNoise) => {
+            ordered_dither(pix, &mut out, w, tone, blue_noise_mask(), BLUE_NOISE_SIZE)
+        }
+        Some(DitherMethod::FloydSteinberg) => {
+            error_diffusion(pix, &mut out, w, h, tone, &FLOYD_STEINBERG)
+        }
+        Some(DitherMethod::JarvisJudiceNinke) => {
+            error_diffusion(pix, &mut out, w, h, tone, &JARVIS_JUDICE_NINKE)
+        }
+        Some(DitherMethod::Stucki) => error_diffusion(pix, &mut out, w, h, tone, &STUCKI),
+        Some(DitherMethod::Sierra) => error_diffusion(pix, &mut out, w, h, tone, &SIERRA),
+    }
+
+    out
+}
+
+// ---------------- Ordered dithering ----------------
+
+/// Classic 8x8 Bayer index matrix (ranks 0..63).
+const BAYER8: [u16; 64] = [
+    0, 32, 8, 40, 2, 34, 10, 42, //
+    48, 16, 56, 24, 50, 18, 58, 26, //
+    12, 44, 4, 36, 14, 46, 6, 38, //
+    60, 28, 52, 20, 62, 30, 54, 22, //
+    3, 35, 11, 43, 1, 33, 9, 41, //
+    51, 19, 59, 27, 49, 17, 57, 25, //
+    15, 47, 7, 39, 13, 45, 5, 37, //
+    63, 31, 55, 23, 61, 29, 53, 21, //
+];
+
+/// Threshold dithering against a tiled rank matrix of `size` x `size`.
+///
+/// The same threshold is used for all three channels so that neutral greys
+/// stay neutral (no chroma noise on paper backgrounds).
+fn ordered_dither(
+    pix: &[RGB16],
+    out: &mut [RGB8],
+    w: usize,
+    tone: ToneMap,
+    ranks: &[u16],
+    size: usize,
+) {
+    let levels = (size * size) as f32;
+    out.par_chunks_mut(w)
+        .zip(pix.par_chunks(w))
+        .enumerate()
+        .for_each(|(y, (orow, prow))| {
+            let mrow = &ranks[(y % size) * size..(y % size + 1) * size];
+            for (x, (o, p)) in orow.iter_mut().zip(prow).enumerate() {
+                // Threshold in (0, 1); floor(v + t) rounds up with probability frac(v).
+                let t = (mrow[x % size] as f32 + 0.5) / levels;
+                let q = |v: u16| (level8(v, tone) + t).floor().clamp(0.0, 255.0) as u8;
+                *o = RGB8::new(q(p.r), q(p.g), q(p.b));
+            }
+        });
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Deterministic blue-noise rank matrix, built once per process.
+fn blue_noise_mask() -> &'static [u16] {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    MASK.get_or_init(|| build_blue_noise_mask(BLUE_NOISE_SIZE, 1.5))
+}
+
+/// Void-and-cluster (Ulichney 1993) on a toroidal `n` x `n` grid.
+///
+/// The initial pattern is seeded from a fixed xorshift state, so the mask is
+/// identical on every run and platform.
+fn build_blue_noise_mask(n: usize, sigma: f32) -> Vec<u16> {
+    let len = n * n;
+
+    // Gaussian energy kernel over toroidal offsets.
+    let mut kernel = vec![0f32; len];
+    for dy in 0..n {
+        for dx in 0..n {
+            let ddx = dx.min(n - dx) as f32;
+            let ddy = dy.min(n - dy) as f32;
+            kernel[dy * n + dx] = (-(ddx * ddx + ddy * ddy) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+
+    let update = |energy: &mut [f32], pos: usize, sign: f32| {
+        let (px, py) = (pos % n, pos / n);
+        for y in 0..n {
+            let ky = (y + n - py) % n;
+            for x in 0..n {
+                let kx = (x + n - px) % n;
+                energy[y * n + x] += sign * kernel[ky * n + kx];
+            }
+        }
+    };
+    // Tightest cluster: the set pixel with the highest energy.
+    let tightest = |pat: &[bool], energy: &[f32]| -> usize {
+        (0..len)
+            .filter(|&i| pat[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .expect("pattern has set pixels")
+    };
+    // Largest void: the empty pixel with the lowest energy.
+    let largest_void = |pat: &[bool], energy: &[f32]| -> usize {
+        (0..len)
+            .filter(|&i| !pat[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .expect("pattern has empty pixels")
+    };
+
+    // Initial random pattern (~10% ones).
+    let mut state: u32 = 0x9E37_79B9;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    let ones = len / 10;
+    let mut pat = vec![false; len];
+    let mut energy = vec![0f32; len];
+    let mut placed = 0;
+    while placed < ones {
+        let i = next() as usize % len;
+        if !pat[i] {
+            pat[i] = true;
+            update(&mut energy, i, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Relax into a blue-noise prototype pattern.
+    loop {
+        let c = tightest(&pat, &energy);
+        pat[c] = false;
+        update(&mut energy, c, -1.0);
+        let v = largest_void(&pat, &energy);
+        pat[v] = true;
+        update(&mut energy, v, 1.0);
+        if v == c {
+            break;
+        }
+    }
+
+    let mut rank = vec![0u16; len];
+
+    // Phase 1: rank the prototype's ones by removing tightest clusters.
+    {
+        let mut p = pat.clone();
+        let mut e = energy.clone();
+        for r in (0..ones).rev() {
+            let c = tightest(&p, &e);
+            p[c] = false;
+            update(&mut e, c, -1.0);
+            rank[c] = r as u16;
+        }
+    }
+
+    // Phase 2: fill the largest voids until every pixel is ranked.
+    for r in ones..len {
+        let v = largest_void(&pat, &energy);
+        pat[v] = true;
+        update(&mut energy, v, 1.0);
+        rank[v] = r as u16;
+    }
+
+    rank
+}
+
+// ---------------- Error diffusion ----------------
+
+/// Error-diffusion kernel: `(dx, dy, weight)` taps for a left-to-right scan
+/// (mirrored on right-to-left rows), normalised by `divisor`.
+struct Kernel {
+    taps: &'static [(isize, usize, f32)],
+    divisor: f32,
+}
+
+const FLOYD_STEINBERG: Kernel = Kernel {
+    taps: &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)],
+    divisor: 16.0,
+};
+
+const JARVIS_JUDICE_NINKE: Kernel = Kernel {
+    taps: &[
+        (1, 0, 7.0),
+        (2, 0, 5.0),
+        (-2, 1, 3.0),
+        (-1, 1, 5.0),
+        (0, 1, 7.0),
+        (1, 1, 5.0),
+        (2, 1, 3.0),
+        (-2, 2, 1.0),
+        (-1, 2, 3.0),
+        (0, 2, 5.0),
+        (1, 2, 3.0),
+        (2, 2, 1.0),
+    ],
+    divisor: 48.0,
+};
+
+const STUCKI: Kernel = Kernel {
+    taps: &[
+        (1, 0, 8.0),
+        (2, 0, 4.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 8.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-2, 2, 1.0),
+        (-1, 2, 2.0),
+        (0, 2, 4.0),
+        (1, 2, 2.0),
+        (2, 2, 1.0),
+    ],
+    divisor: 42.0,
+};
+
+const SIERRA: Kernel = Kernel {
+    taps: &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    divisor: 32.0,
+};
+
+/// Kernels reach at most 2 columns sideways and 2 rows down.
+const KERNEL_REACH: usize = 2;
+
+/// Error diffusion runs independently per band of this many rows.
+///
+/// The band size is fixed (never derived from the thread count), so output is
+/// bit-identical on 1 or N threads.
+const BAND_ROWS: usize = 256;
+
+/// Rows above each band that are diffused but not written, so that the error
+/// state entering the band is already settled and no seam is visible.
+const BAND_WARMUP_ROWS: usize = 16;
+
+/// Serpentine error diffusion with f32 error buffers (no truncation bias),
+/// parallelised over fixed bands of `BAND_ROWS` rows.
+fn error_diffusion(
+    pix: &[RGB16],
+    out: &mut [RGB8],
+    w: usize,
+    h: usize,
+    tone: ToneMap,
+    kernel: &Kernel,
+) {
+    debug_assert_eq!(out.len(), w * h);
+
+    let taps: Vec<(isize, usize, f32)> = kernel
+        .taps
+        .iter()
+        .map(|&(dx, dy, wt)| (dx, dy, wt / kernel.divisor))
+        .collect();
+
+    out.par_chunks_mut(BAND_ROWS * w)
+        .enumerate()
+        .for_each(|(band, oband)| {
+            let y0 = band * BAND_ROWS;
+            let y1 = y0 + oband.len() / w;
+            diffuse_band(
+                pix,
+                oband,
+                w,
+                y0.saturating_sub(BAND_WARMUP_ROWS),
+                y0,
+                y1,
+                tone,
+                &taps,
+            );
+        });
+}
+
+/// Diffuse rows `start..y1`, writing only rows `y0..y1` into `out` (which
+/// holds exactly those rows). Scan direction follows the global row parity.
+///
+/// Errors are kept in a ring of `KERNEL_REACH + 1` padded scanlines, so memory
+/// stays O(width) regardless of band height.
+#[allow(clippy::too_many_arguments)]
+fn diffuse_band(
+    pix: &[RGB16],
+    out: &mut [RGB8],
+    w: usize,
+    start: usize,
+    y0: usize,
+    y1: usize,
+    tone: ToneMap,
+    taps: &[(isize, usize, f32)],
+) {
+    let rows = KERNEL_REACH + 1;
+    let stride = (w + 2 * KERNEL_REACH) * 3;
+    let mut err = vec![0f32; rows * stride];
+
+    for y in start..y1 {
+        let reverse = y % 2 == 1;
+        let cur = (y % rows) * stride;
+
+        for i in 0..w {
+            let x = if reverse { w - 1 - i } else { i };
+            let p = pix[y * w + x];
+            let eoff = cur + (x + KERNEL_REACH) * 3;
+
+            let want = [
+                level8(p.r, tone) + err[eoff],
+                level8(p.g, tone) + err[eoff + 1],
+                level8(p.b, tone) + err[eoff + 2],
+            ];
+            let q = want.map(|v| v.round().clamp(0.0, 255.0));
+            if y >= y0 {
+                out[(y - y0) * w + x] = RGB8::new(q[0] as u8, q[1] as u8, q[2] as u8);
+            }
+
+            for &(dx, dy, wt) in taps {
+                let dx = if reverse { -dx } else { dx };
+                // Padding absorbs taps that fall off the left/right edge.
+                let col = (x + KERNEL_REACH) as isize + dx;
+                let off = ((y + dy) % rows) * stride + col as usize * 3;
+                for c in 0..3 {
+                    err[off + c] += (want[c] - q[c]) * wt;
+                }
+            }
+        }
+
+        // This row's slot is reused for row y + rows.
+        err[cur..cur + stride].fill(0.0);
+    }
+}
@@ -0,0 +1,246 @@
+// ---------------- Resampling (--resize) ----------------
+//
+// Separable resampling of float RGB. The caller converts the pixels to the
+// linear-light version of the working space first (`icc::linear_rgb`), so
+// the kernels average light, not encoded values.
+
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use rgb::RGB;
+use std::f64::consts::PI;
+
+/// Target size of --resize.
+#[derive(Debug, Copy, Clone)]
+pub enum ResizeSpec {
+    /// Downscale so that the long edge is at most this many pixels
+    LongEdge(u32),
+    /// Scale both axes by this percentage
+    Percent(f64),
+    /// Scale to this resolution (needs the input resolution)
+    Dpi(f64),
+}
+
+pub fn parse_resize(s: &str) -> Result<ResizeSpec, String> {
+    let positive = |v: &str| -> Result<f64, String> {
+        let v: f64 = v.parse().map_err(|e| format!("'{v}': {e}"))?;
+        if v > 0.0 && v.is_finite() {
+            Ok(v)
+        } else {
+            Err(format!("{v} must be positive"))
+        }
+    };
+    if let Some(px) = s.strip_prefix("long-edge=") {
+        let px: u32 = px.parse().map_err(|e| format!("long-edge=<px>: {e}"))?;
+        if px == 0 {
+            return Err("long-edge must be at least 1 pixel".into());
+        }
+        Ok(ResizeSpec::LongEdge(px))
+    } else if let Some(dpi) = s.strip_prefix("dpi=") {
+        Ok(ResizeSpec::Dpi(positive(dpi)?))
+    } else if let Some(pct) = s.strip_suffix('%') {
+        Ok(ResizeSpec::Percent(positive(pct)?))
+    } else {
+        Err(format!("'{s}' is not long-edge=<px>, <percent>% or dpi=<dpi>"))
+    }
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum ResizeFilter {
+    /// Lanczos, 3 lobes (sharp; slight ringing at hard edges)
+    Lanczos3,
+    /// Mitchell–Netravali cubic, B = C = 1/3 (softer, no visible ringing)
+    Mitchell,
+}
+
+/// Working space in which --resize resamples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ResizeSpace {
+    /// Linear light of the input profile, before the ICC transform
+    Input,
+    /// Linear light of the output profile, after the ICC transform
+    Output,
+}
+
+impl ResizeFilter {
+    /// Kernel radius in source pixels at scale 1.
+    fn support(self) -> f64 {
+        match self {
+            ResizeFilter::Lanczos3 => 3.0,
+            ResizeFilter::Mitchell => 2.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeFilter::Lanczos3 => {
+                if x < 1e-12 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Mitchell => {
+                const B: f64 = 1.0 / 3.0;
+                const C: f64 = 1.0 / 3.0;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B))
+                        / 6.0
+                } else if x < 2.0 {
+                    ((-B - 6.0 * C) * x * x * x
+                        + (6.0 * B + 30.0 * C) * x * x
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C))
+                        / 6.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Output size for `spec`, or `None` when the image already fits.
+///
+/// `dpi` is the input resolution (x, y) in dots per inch, if known.
+pub fn target_size(spec: ResizeSpec, w: u32, h: u32, dpi: Option<(f64, f64)>) -> Result<Option<(u32, u32)>> {
+    let scaled = |n: u32, f: f64| ((n as f64 * f).round() as u32).max(1);
+    let size = match spec {
+        ResizeSpec::LongEdge(max) => {
+            let long = w.max(h);
+            if long <= max {
+                return Ok(None);
+            }
+            let f = max as f64 / long as f64;
+            if w >= h { (max, scaled(h, f)) } else { (scaled(w, f), max) }
+        }
+        ResizeSpec::Percent(pct) => (scaled(w, pct / 100.0), scaled(h, pct / 100.0)),
+        ResizeSpec::Dpi(target) => {
+            let Some((x_dpi, y_dpi)) = dpi else {
+                bail!("--resize dpi=<dpi> needs the input resolution (see --missing-resolution assume=<dpi>)");
+            };
+            (scaled(w, target / x_dpi), scaled(h, target / y_dpi))
+        }
+    };
+    Ok((size != (w, h)).then_some(size))
+}
+
+/// Contributions of the source pixels to each output pixel along one axis:
+/// (first source index, normalised weights).
+fn axis_weights(src_len: u32, dst_len: u32, filter: ResizeFilter) -> Vec<(usize, Vec<f32>)> {
+    let scale = dst_len as f64 / src_len as f64;
+    // Downscaling widens the kernel so that it also low-pass filters.
+    let stretch = (1.0 / scale).max(1.0);
+    let radius = filter.support() * stretch;
+
+    (0..dst_len)
+        .map(|i| {
+            let centre = (i as f64 + 0.5) / scale - 0.5;
+            let first = ((centre - radius).ceil().max(0.0)) as usize;
+            let last = ((centre + radius).floor() as usize).min(src_len as usize - 1);
+            let mut weights: Vec<f64> = (first..=last)
+                .map(|j| filter.weight((j as f64 - centre) / stretch))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            if sum.abs() > 1e-12 {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            }
+            (first, weights.into_iter().map(|w| w as f32).collect())
+        })
+        .collect()
+}
+
+/// Resample `w`×`h` float RGB to `nw`×`nh` (horizontal pass, then vertical).
+pub fn resample(src: &[RGB<f32>], w: u32, h: u32, nw: u32, nh: u32, filter: ResizeFilter) -> Vec<RGB<f32>> {
+    let (w, nw_us) = (w as usize, nw as usize);
+    let zero = RGB::new(0.0f32, 0.0, 0.0);
+
+    let xw = axis_weights(w as u32, nw, filter);
+    let mut tmp = vec![zero; nw_us * h as usize];
+    tmp.par_chunks_mut(nw_us).enumerate().for_each(|(y, row)| {
+        let src_row = &src[y * w..(y + 1) * w];
+        for (out, (first, weights)) in row.iter_mut().zip(&xw) {
+            let mut acc = zero;
+            for (p, &k) in src_row[*first..].iter().zip(weights) {
+                acc.r += p.r * k;
+                acc.g += p.g * k;
+                acc.b += p.b * k;
+            }
+            *out = acc;
+        }
+    });
+
+    let yw = axis_weights(h, nh, filter);
+    let mut out = vec![zero; nw_us * nh as usize];
+    out.par_chunks_mut(nw_us).zip(&yw).for_each(|(row, (first, weights))| {
+        for (j, &k) in weights.iter().enumerate() {
+            let src_row = &tmp[(first + j) * nw_us..(first + j + 1) * nw_us];
+            for (o, p) in row.iter_mut().zip(src_row) {
+                o.r += p.r * k;
+                o.g += p.g * k;
+                o.b += p.b * k;
+            }
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resize_specs() {
+        assert!(matches!(parse_resize("long-edge=3000"), Ok(ResizeSpec::LongEdge(3000))));
+        assert!(matches!(parse_resize("50%"), Ok(ResizeSpec::Percent(p)) if p == 50.0));
+        assert!(matches!(parse_resize("12.5%"), Ok(ResizeSpec::Percent(p)) if p == 12.5));
+        assert!(matches!(parse_resize("dpi=300"), Ok(ResizeSpec::Dpi(d)) if d == 300.0));
+    }
+
+    #[test]
+    fn parse_resize_rejects_bad_values() {
+        for s in ["long-edge=0", "long-edge=-1", "long-edge=", "0%", "-5%", "dpi=0", "dpi=nan", "dpi=inf", "300", ""] {
+            assert!(parse_resize(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn axis_weights_are_normalised_and_in_range() {
+        for filter in [ResizeFilter::Lanczos3, ResizeFilter::Mitchell] {
+            for (src, dst) in [(10, 5), (7, 3), (5, 10), (1001, 17), (3, 1), (1, 4)] {
+                let weights = axis_weights(src, dst, filter);
+                assert_eq!(weights.len(), dst as usize);
+                for (first, w) in &weights {
+                    assert!(first + w.len() <= src as usize, "{filter:?} {src}->{dst}");
+                    let sum: f32 = w.iter().sum();
+                    assert!((sum - 1.0).abs() < 1e-5, "{filter:?} {src}->{dst}: sum {sum}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lanczos_identity_weights() {
+        // At scale 1 the Lanczos kernel is zero at every other integer offset
+        for (i, (first, w)) in axis_weights(6, 6, ResizeFilter::Lanczos3).iter().enumerate() {
+            for (j, &k) in w.iter().enumerate() {
+                let expected = if first + j == i { 1.0 } else { 0.0 };
+                assert!((k - expected).abs() < 1e-6, "output {i}, source {}: {k}", first + j);
+            }
+        }
+    }
+
+    #[test]
+    fn downscale_by_two_is_symmetric() {
+        // Output pixel 1 of 8 -> 4 is centred between source pixels 2 and 3
+        let weights = axis_weights(8, 4, ResizeFilter::Mitchell);
+        let (first, w) = &weights[1];
+        let at = |j: usize| w[j - first];
+        assert!((at(2) - at(3)).abs() < 1e-6);
+        assert!((at(1) - at(4)).abs() < 1e-6);
+    }
+}
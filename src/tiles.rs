@@ -0,0 +1,348 @@
+// ---------------- Tile pyramids (--tiles) ----------------
+//
+// Static tile sets cut from the converted image. Each level is resampled
+// by the caller; this module only lays out and encodes the tiles (8-bit
+// sRGB JPEG with the ICC profile embedded) and writes the descriptor.
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+use rayon::prelude::*;
+use rgb::RGB8;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TileFormat {
+    /// IIIF Image API 3.0, level 0 (`<out>/info.json` + tile tree)
+    Iiif,
+    /// Deep Zoom (`<out>.dzi` + `<out>_files/`), e.g. for OpenSeadragon
+    Dzi,
+    /// Zoomify (`<out>/ImageProperties.xml` + `TileGroup*/`)
+    Zoomify,
+}
+
+impl TileFormat {
+    /// Conventional tile edge of the format.
+    pub fn default_tile_size(self) -> u32 {
+        match self {
+            TileFormat::Iiif => 512,
+            TileFormat::Dzi => 254,
+            TileFormat::Zoomify => 256,
+        }
+    }
+}
+
+pub struct TileOptions<'a> {
+    /// Tile edge in level pixels
+    pub size: u32,
+    /// Pixels shared with each neighbouring tile (DZI only)
+    pub overlap: u32,
+    /// JPEG quality (1..=100)
+    pub quality: u8,
+    /// ICC profile embedded in every tile
+    pub icc: &'a [u8],
+}
+
+/// One level of the pyramid: the full image downscaled by `scale`.
+#[derive(Debug, Copy, Clone)]
+pub struct Level {
+    pub scale: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Levels of a `w`×`h` image, full resolution first.
+///
+/// IIIF uses `factors` (default: powers of two until the image fits in one
+/// tile) with sizes rounded up; DZI halves down to 1×1, rounding up; Zoomify
+/// halves until the image fits in one tile, rounding down.
+pub fn levels(format: TileFormat, w: u32, h: u32, size: u32, factors: Option<&[u32]>) -> Vec<Level> {
+    let ceil = |scale: u32| Level { scale, width: w.div_ceil(scale), height: h.div_ceil(scale) };
+    match format {
+        TileFormat::Iiif => {
+            let mut factors = factors.map(<[u32]>::to_vec).unwrap_or_else(|| {
+                let mut s = 1u32;
+                let mut factors = vec![s];
+                while w.max(h).div_ceil(s) > size && s < 1 << 30 {
+                    s *= 2;
+                    factors.push(s);
+                }
+                factors
+            });
+            factors.sort_unstable();
+            factors.dedup();
+            factors.into_iter().map(ceil).collect()
+        }
+        TileFormat::Dzi => {
+            let long = w.max(h);
+            let max_level = u32::BITS - long.saturating_sub(1).leading_zeros();
+            (0..=max_level.min(31)).map(|l| ceil(1 << l)).collect()
+        }
+        TileFormat::Zoomify => {
+            let mut levels = vec![Level { scale: 1, width: w, height: h }];
+            let mut scale = 1u32;
+            while (w / scale > size || h / scale > size) && scale < 1 << 30 {
+                scale *= 2;
+                levels.push(Level { scale, width: (w / scale).max(1), height: (h / scale).max(1) });
+            }
+            levels
+        }
+    }
+}
+
+/// File that marks a complete tile set at `output`.
+pub fn descriptor_path(format: TileFormat, output: &Path) -> PathBuf {
+    match format {
+        TileFormat::Iiif => output.join("info.json"),
+        TileFormat::Dzi => sibling(output, ".dzi"),
+        TileFormat::Zoomify => output.join("ImageProperties.xml"),
+    }
+}
+
+/// `output` with `suffix` appended to its file name.
+fn sibling(output: &Path, suffix: &str) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    output.with_file_name(name)
+}
+
+/// Columns and rows of tiles at `level`.
+fn grid(level: &Level, size: u32) -> (u32, u32) {
+    (level.width.div_ceil(size), level.height.div_ceil(size))
+}
+
+/// Write the tiles of `levels[n]` (`pix`, row by row) of a `w`×`h` image.
+///
+/// IIIF tiles go to `{x},{y},{w},{h}/{tw},{th}/0/default.jpg` with the region
+/// in full-image pixels; a level that fits in one tile is also written as
+/// `full/{w},{h}/0/default.jpg` (the `sizes` of `info.json`), and the full
+/// resolution level as `full/max/0/default.jpg` too. DZI tiles go to
+/// `<out>_files/{level}/{col}_{row}.jpg` (level 0 is 1×1), Zoomify tiles to
+/// `TileGroup{i / 256}/{tier}-{col}-{row}.jpg`, numbered from the smallest tier.
+#[allow(clippy::too_many_arguments)]
+pub fn write_level(
+    format: TileFormat,
+    output: &Path,
+    w: u32,
+    h: u32,
+    levels: &[Level],
+    n: usize,
+    pix: &[RGB8],
+    opts: &TileOptions,
+) -> Result<()> {
+    let level = &levels[n];
+    let (lw, lh, scale) = (level.width, level.height, level.scale);
+    let ts = opts.size;
+    let (cols, rows) = grid(level, ts);
+    // Zoomify numbers tiles across tiers, smallest tier first
+    let first_index: u32 = levels[n + 1..]
+        .iter()
+        .map(|l| {
+            let (c, r) = grid(l, ts);
+            c * r
+        })
+        .sum();
+    let files = sibling(output, "_files").join((levels.len() - 1 - n).to_string());
+
+    let tiles: Vec<(u32, u32)> = (0..rows).flat_map(|r| (0..cols).map(move |c| (c, r))).collect();
+    tiles.par_iter().try_for_each(|&(c, r)| {
+        let (lx, ly) = (c * ts, r * ts);
+        let (tw, th) = (ts.min(lw - lx), ts.min(lh - ly));
+        match format {
+            TileFormat::Iiif => {
+                let (x, y) = (lx * scale, ly * scale);
+                let (rw, rh) = ((ts * scale).min(w - x), (ts * scale).min(h - y));
+                let path = output
+                    .join(format!("{x},{y},{rw},{rh}"))
+                    .join(format!("{tw},{th}"))
+                    .join("0")
+                    .join("default.jpg");
+                write_jpeg(&path, crop(pix, lw, lx, ly, tw, th), tw, th, opts)
+            }
+            TileFormat::Dzi => {
+                // Overlap on the inner edges only
+                let (x0, y0) = (lx.saturating_sub(opts.overlap), ly.saturating_sub(opts.overlap));
+                let x1 = (lx + tw + opts.overlap).min(lw);
+                let y1 = (ly + th + opts.overlap).min(lh);
+                let path = files.join(format!("{c}_{r}.jpg"));
+                write_jpeg(&path, crop(pix, lw, x0, y0, x1 - x0, y1 - y0), x1 - x0, y1 - y0, opts)
+            }
+            TileFormat::Zoomify => {
+                let index = first_index + r * cols + c;
+                let tier = levels.len() - 1 - n;
+                let path = output
+                    .join(format!("TileGroup{}", index / 256))
+                    .join(format!("{tier}-{c}-{r}.jpg"));
+                write_jpeg(&path, crop(pix, lw, lx, ly, tw, th), tw, th, opts)
+            }
+        }
+    })?;
+
+    if format == TileFormat::Iiif && lw <= ts && lh <= ts {
+        let mut sizes = vec![format!("{lw},{lh}")];
+        if (lw, lh) == (w, h) {
+            sizes.push("max".into());
+        }
+        for size in sizes {
+            let path = output.join("full").join(size).join("0").join("default.jpg");
+            write_jpeg(&path, crop(pix, lw, 0, 0, lw, lh), lw, lh, opts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the descriptor of the tile set (see `descriptor_path`).
+///
+/// The IIIF `id` is `<iiif_base>/<directory name>`.
+pub fn write_descriptor(
+    format: TileFormat,
+    output: &Path,
+    iiif_base: Option<&str>,
+    w: u32,
+    h: u32,
+    levels: &[Level],
+    opts: &TileOptions,
+) -> Result<()> {
+    let ts = opts.size;
+    let text = match format {
+        TileFormat::Iiif => {
+            let base = iiif_base.ok_or_else(|| anyhow!("--tiles iiif requires --iiif-base <URL>"))?;
+            let name = output
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow!("Invalid tile directory name: {}", output.display()))?;
+            let id = format!("{}/{name}", base.trim_end_matches('/'));
+
+            let mut sizes: Vec<(u32, u32)> = levels
+                .iter()
+                .filter(|l| l.width <= ts && l.height <= ts)
+                .map(|l| (l.width, l.height))
+                .collect();
+            sizes.sort_unstable();
+            let sizes = sizes
+                .iter()
+                .map(|(sw, sh)| format!("    {{ \"width\": {sw}, \"height\": {sh} }}"))
+                .collect::<Vec<_>>()
+                .join(",\n");
+            let factors = levels.iter().map(|l| l.scale.to_string()).collect::<Vec<_>>().join(", ");
+            format!(
+                r#"{{
+  "@context": "http://iiif.io/api/image/3/context.json",
+  "id": "{id}",
+  "type": "ImageService3",
+  "protocol": "http://iiif.io/api/image",
+  "profile": "level0",
+  "width": {w},
+  "height": {h},
+  "sizes": [
+{sizes}
+  ],
+  "tiles": [
+    {{ "width": {ts}, "height": {ts}, "scaleFactors": [{factors}] }}
+  ]
+}}
+"#,
+                id = json_escape(&id),
+            )
+        }
+        TileFormat::Dzi => format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="jpg" Overlap="{}" TileSize="{ts}">
+  <Size Width="{w}" Height="{h}"/>
+</Image>
+"#,
+            opts.overlap
+        ),
+        TileFormat::Zoomify => {
+            let tiles: u32 = levels
+                .iter()
+                .map(|l| {
+                    let (c, r) = grid(l, ts);
+                    c * r
+                })
+                .sum();
+            format!(
+                "<IMAGE_PROPERTIES WIDTH=\"{w}\" HEIGHT=\"{h}\" NUMTILES=\"{tiles}\" NUMIMAGES=\"1\" VERSION=\"1.8\" TILESIZE=\"{ts}\" />\n"
+            )
+        }
+    };
+
+    let path = descriptor_path(format, output);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
+    }
+    fs::write(&path, text).with_context(|| format!("Write {}", path.display()))
+}
+
+fn json_escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// RGB bytes of the `tw`×`th` rectangle at (`x`, `y`) of an image `stride` pixels wide.
+fn crop(pix: &[RGB8], stride: u32, x: u32, y: u32, tw: u32, th: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(tw as usize * th as usize * 3);
+    for row in y..y + th {
+        let start = row as usize * stride as usize + x as usize;
+        for p in &pix[start..start + tw as usize] {
+            out.extend_from_slice(&[p.r, p.g, p.b]);
+        }
+    }
+    out
+}
+
+fn write_jpeg(path: &Path, rgb: Vec<u8>, w: u32, h: u32, opts: &TileOptions) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Create {}", parent.display()))?;
+    }
+    let f = fs::File::create(path).with_context(|| format!("Create {}", path.display()))?;
+    let mut enc = JpegEncoder::new_with_quality(BufWriter::new(f), opts.quality);
+    enc.set_icc_profile(opts.icc.to_vec())?;
+    enc.write_image(&rgb, w, h, ExtendedColorType::Rgb8)
+        .with_context(|| format!("Write tile {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(levels: &[Level]) -> Vec<(u32, u32, u32)> {
+        levels.iter().map(|l| (l.scale, l.width, l.height)).collect()
+    }
+
+    #[test]
+    fn dzi_levels_round_up_to_1x1() {
+        let levels = levels(TileFormat::Dzi, 5, 3, 254, None);
+        assert_eq!(sizes(&levels), [(1, 5, 3), (2, 3, 2), (4, 2, 1), (8, 1, 1)]);
+    }
+
+    #[test]
+    fn dzi_levels_of_a_single_pixel() {
+        assert_eq!(sizes(&levels(TileFormat::Dzi, 1, 1, 254, None)), [(1, 1, 1)]);
+    }
+
+    #[test]
+    fn zoomify_levels_round_down_until_one_tile() {
+        let levels = levels(TileFormat::Zoomify, 1001, 601, 256, None);
+        assert_eq!(sizes(&levels), [(1, 1001, 601), (2, 500, 300), (4, 250, 150)]);
+    }
+
+    #[test]
+    fn iiif_levels_round_up_until_one_tile() {
+        let levels = levels(TileFormat::Iiif, 1001, 601, 512, None);
+        assert_eq!(sizes(&levels), [(1, 1001, 601), (2, 501, 301)]);
+    }
+
+    #[test]
+    fn iiif_levels_from_scale_factors() {
+        let levels = levels(TileFormat::Iiif, 1001, 601, 512, Some(&[4, 1, 4]));
+        assert_eq!(sizes(&levels), [(1, 1001, 601), (4, 251, 151)]);
+    }
+}